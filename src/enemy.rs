@@ -1,7 +1,14 @@
 use bevy::prelude::*;
 use heron::{prelude::*, rapier_plugin::PhysicsWorld};
 
-use crate::{levels::map::MapInitData, player::PlayerStats, utils::CommonHandles, GameState};
+use crate::{
+    gun::WeaponDef,
+    health::{Health, Knockback},
+    levels::{campaign::Campaign, map::MapInitData, objective::ObjectiveProgress},
+    player::PlayerStats,
+    totem::Slowed,
+    utils::CommonHandles,
+};
 
 pub struct EnemyPlugin;
 
@@ -12,7 +19,7 @@ impl Plugin for EnemyPlugin {
                 // This apparently removes the GameState condition
                 //.with_run_criteria(FixedTimestep::steps_per_second(60.0))
                 .with_system(enemy_follow_player)
-                .with_system(despawn_enemy_on_collision), //.with_system(check_enemy_visibility),
+                .with_system(despawn_dead_enemies), //.with_system(check_enemy_visibility),
         );
     }
 }
@@ -23,11 +30,65 @@ pub struct EnemyStats {
     pub speed: f32,
 }
 
+#[derive(Component, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EnemyKind {
+    Skeleton,
+    /// Fast but fragile
+    Ghoul,
+    /// Slow, big and hits hard
+    Brute,
+}
+
+impl EnemyKind {
+    fn max_health(&self) -> f32 {
+        match self {
+            EnemyKind::Skeleton => 3.0,
+            EnemyKind::Ghoul => 2.0,
+            EnemyKind::Brute => 9.0,
+        }
+    }
+
+    fn stats(&self) -> EnemyStats {
+        match self {
+            EnemyKind::Skeleton => EnemyStats {
+                damage: 50.0,
+                speed: 30.0,
+            },
+            EnemyKind::Ghoul => EnemyStats {
+                damage: 25.0,
+                speed: 60.0,
+            },
+            EnemyKind::Brute => EnemyStats {
+                damage: 100.0,
+                speed: 20.0,
+            },
+        }
+    }
+
+    fn color(&self) -> Color {
+        match self {
+            EnemyKind::Skeleton => Color::WHITE,
+            EnemyKind::Ghoul => Color::rgb(0.6, 1.0, 0.6),
+            EnemyKind::Brute => Color::rgb(1.0, 0.6, 0.6),
+        }
+    }
+
+    fn scale(&self) -> f32 {
+        match self {
+            EnemyKind::Brute => 1.5,
+            _ => 1.0,
+        }
+    }
+}
+
 pub fn enemy_follow_player(
     players: Query<(&Transform, &PlayerStats)>,
-    mut enemies: Query<(&mut Velocity, &Transform, &EnemyStats)>,
+    mut enemies: Query<
+        (&mut Velocity, &Transform, &EnemyStats, Option<&Slowed>),
+        Without<Knockback>,
+    >,
 ) {
-    for (mut vel, enemy_trans, enemy_stats) in enemies.iter_mut() {
+    for (mut vel, enemy_trans, enemy_stats, slowed) in enemies.iter_mut() {
         // println!("ticking enemy at {:?}", enemy_trans.translation);
         // Find closest player pos
         if let Some((closest_player_trans, _)) = players.iter().min_by_key(|(player_trans, _)| {
@@ -40,27 +101,41 @@ pub fn enemy_follow_player(
             let direction =
                 (enemy_trans.translation - closest_player_trans.translation).normalize();
             // Make the enemy go there
-            vel.linear = direction * enemy_stats.speed * -1.0;
+            let speed = enemy_stats.speed * slowed.map_or(1.0, |slowed| slowed.0);
+            vel.linear = direction * speed * -1.0;
         }
     }
 }
 
-pub fn spawn_enemy(commands: &mut Commands, common_handles: &CommonHandles, position: Vec2) {
+pub fn spawn_enemy(
+    commands: &mut Commands,
+    common_handles: &CommonHandles,
+    position: Vec2,
+    kind: EnemyKind,
+) {
     commands
         .spawn()
         .insert_bundle(SpriteSheetBundle {
-            sprite: TextureAtlasSprite::new(40),
+            sprite: TextureAtlasSprite {
+                color: kind.color(),
+                ..TextureAtlasSprite::new(40)
+            },
             texture_atlas: common_handles.player_sprites.clone(),
-            transform: Transform::from_translation(position.extend(1.0)),
+            transform: Transform {
+                translation: position.extend(1.0),
+                scale: Vec3::splat(kind.scale()),
+                ..Default::default()
+            },
             ..Default::default()
         })
-        .insert(EnemyStats {
-            damage: 50.0,
-            speed: 30.0,
-        })
+        .insert(kind)
+        .insert(kind.stats())
+        .insert(Health::new(kind.max_health()))
         .insert(RigidBody::Dynamic)
         .insert(RotationConstraints::lock())
-        .insert(CollisionShape::Sphere { radius: 10.0 })
+        .insert(CollisionShape::Sphere {
+            radius: 10.0 * kind.scale(),
+        })
         .insert(
             CollisionLayers::none()
                 .with_group(crate::GameLayers::Enemies)
@@ -69,37 +144,40 @@ pub fn spawn_enemy(commands: &mut Commands, common_handles: &CommonHandles, posi
                     crate::GameLayers::Player,
                     crate::GameLayers::Bullets,
                     crate::GameLayers::Enemies,
+                    crate::GameLayers::Relic,
+                    crate::GameLayers::Grenades,
                 ]),
         )
         .insert(Velocity::default());
 }
 
-fn despawn_enemy_on_collision(
+fn despawn_dead_enemies(
     mut commands: Commands,
     time: Res<Time>,
+    asset_server: Res<AssetServer>,
+    weapons: Res<Assets<WeaponDef>>,
+    campaign: Res<Campaign>,
     mut map_init_data: ResMut<MapInitData>,
-    mut game_state: ResMut<State<GameState>>,
-    mut events: EventReader<CollisionEvent>,
+    mut progress: ResMut<ObjectiveProgress>,
+    enemies: Query<(Entity, &Health, &Transform), (With<EnemyStats>, Changed<Health>)>,
 ) {
     // Fixme not at all the right place for this but that's life ya know?
     map_init_data.timer += time.delta();
-    events.iter().filter(|e| e.is_started()).for_each(|ev| {
-        let (e1, e2) = ev.rigid_body_entities();
-        let (l1, l2) = ev.collision_layers();
-        use crate::GameLayers::*;
-        if l1.contains_group(Enemies) && l2.contains_group(Bullets) {
-            commands.entity(e1).despawn();
-            commands.entity(e2).despawn();
-            map_init_data.kills += 1;
-        } else if l1.contains_group(Bullets) && l2.contains_group(Enemies) {
-            commands.entity(e1).despawn();
-            commands.entity(e2).despawn();
-            map_init_data.kills += 1;
-        }
-        if map_init_data.kills == 50 {
-            let _ = game_state.overwrite_set(GameState::GameWon);
+    let mut rng = rand::thread_rng();
+    for (enemy, health, transform) in enemies.iter() {
+        if health.is_dead() {
+            commands.entity(enemy).despawn();
+            progress.kills += 1;
+            if let Some(item) = campaign.current().roll_enemy_drop(&mut rng) {
+                item.spawn(
+                    &mut commands,
+                    transform.translation.truncate(),
+                    &asset_server,
+                    &weapons,
+                );
+            }
         }
-    });
+    }
 }
 
 fn _check_enemy_visibility(
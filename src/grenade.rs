@@ -0,0 +1,172 @@
+use bevy::prelude::*;
+use heron::{
+    rapier_plugin::{PhysicsWorld, ShapeCastCollisionType},
+    CollisionLayers, CollisionShape, Damping, PhysicMaterial, RigidBody, RotationConstraints,
+    Velocity,
+};
+
+use crate::{enemy::EnemyStats, health::DamageEvent, utils::despawn_all, GameLayers, GameState};
+
+const THROW_SPEED: f32 = 400.0;
+const FUSE_SECS: f32 = 1.5;
+const BLAST_RADIUS: f32 = 80.0;
+const BLAST_DAMAGE: f32 = 6.0;
+const BLAST_KNOCKBACK: f32 = 500.0;
+const BLAST_SECS: f32 = 0.25;
+
+pub struct GrenadePlugin;
+
+impl Plugin for GrenadePlugin {
+    fn build(&self, app: &mut App) {
+        app.add_system_set(
+            SystemSet::on_update(GameState::Playing)
+                .with_system(explode_grenades)
+                .with_system(fade_blasts),
+        )
+        .add_system_set(
+            SystemSet::on_exit(GameState::Playing)
+                .with_system(despawn_all::<Grenade>)
+                .with_system(despawn_all::<Blast>),
+        );
+    }
+}
+
+#[derive(Component)]
+struct Grenade {
+    /// Who threw it, so they get the credit for the damage
+    thrower: Entity,
+    fuse: Timer,
+}
+
+#[derive(Component)]
+struct Blast(Timer);
+
+/// Throws a grenade from `origin` along `aim_direction`. It bounces off walls until
+/// its fuse runs out.
+pub fn throw_grenade(
+    commands: &mut Commands,
+    asset_server: &AssetServer,
+    thrower: Entity,
+    origin: Vec3,
+    aim_direction: Vec2,
+) {
+    let direction = aim_direction.normalize_or_zero();
+    commands
+        .spawn_bundle(SpriteBundle {
+            texture: asset_server.load("images/grenade.png"),
+            sprite: Sprite {
+                custom_size: Some(Vec2::splat(16.0)),
+                ..Default::default()
+            },
+            transform: Transform::from_translation(
+                (origin.truncate() + direction * 12.0).extend(1.2),
+            ),
+            ..Default::default()
+        })
+        .insert(Grenade {
+            thrower,
+            fuse: Timer::from_seconds(FUSE_SECS, false),
+        })
+        .insert(RigidBody::Dynamic)
+        .insert(RotationConstraints::lock())
+        .insert(CollisionShape::Sphere { radius: 6.0 })
+        // Only walls get in the way, it rolls right past enemies
+        .insert(
+            CollisionLayers::none()
+                .with_group(GameLayers::Grenades)
+                .with_mask(GameLayers::World),
+        )
+        .insert(PhysicMaterial {
+            restitution: 0.7,
+            ..Default::default()
+        })
+        .insert(Damping::from_linear(1.5))
+        .insert(Velocity::from_linear(direction.extend(0.0) * THROW_SPEED));
+}
+
+fn explode_grenades(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    time: Res<Time>,
+    physics_world: PhysicsWorld,
+    mut damage_events: EventWriter<DamageEvent>,
+    mut grenades: Query<(Entity, &mut Grenade, &Transform)>,
+    enemies: Query<&Transform, With<EnemyStats>>,
+) {
+    let blast_shape = CollisionShape::Sphere {
+        radius: BLAST_RADIUS,
+    };
+    let blast_layers = CollisionLayers::none()
+        .with_group(GameLayers::Grenades)
+        .with_mask(GameLayers::Enemies);
+
+    for (ent, mut grenade, transform) in grenades.iter_mut() {
+        if !grenade.fuse.tick(time.delta()).finished() {
+            continue;
+        }
+        let center = transform.translation;
+        info!("Grenade thrown by {:?} explodes", grenade.thrower);
+
+        // A shape query only reports one body at a time, so keep asking while
+        // leaving out the ones already hit
+        let mut hit = Vec::new();
+        while let Some(collision) = physics_world.shape_cast_with_filter(
+            &blast_shape,
+            center,
+            Quat::IDENTITY,
+            Vec3::ZERO,
+            blast_layers,
+            |ent| !hit.contains(&ent),
+        ) {
+            let target = match collision {
+                ShapeCastCollisionType::Collision(info) => info.entity,
+                ShapeCastCollisionType::AlreadyPenetrating(info) => info.entity,
+            };
+            hit.push(target);
+        }
+
+        for target in hit {
+            let away = enemies.get(target).map_or(Vec2::ZERO, |enemy| {
+                (enemy.translation - center).truncate().normalize_or_zero()
+            });
+            damage_events.send(DamageEvent {
+                source: grenade.thrower,
+                target,
+                amount: BLAST_DAMAGE,
+                knockback: away * BLAST_KNOCKBACK,
+            });
+        }
+
+        commands.entity(ent).despawn();
+        spawn_blast(&mut commands, &asset_server, center);
+    }
+}
+
+fn spawn_blast(commands: &mut Commands, asset_server: &AssetServer, center: Vec3) {
+    commands
+        .spawn_bundle(SpriteBundle {
+            texture: asset_server.load("images/blast.png"),
+            sprite: Sprite {
+                color: Color::rgba(1.0, 0.6, 0.2, 0.8),
+                custom_size: Some(Vec2::splat(BLAST_RADIUS * 2.0)),
+                ..Default::default()
+            },
+            transform: Transform::from_translation(center.truncate().extend(1.2)),
+            ..Default::default()
+        })
+        .insert(Blast(Timer::from_seconds(BLAST_SECS, false)));
+}
+
+fn fade_blasts(
+    mut commands: Commands,
+    time: Res<Time>,
+    mut blasts: Query<(Entity, &mut Blast, &mut Sprite)>,
+) {
+    for (ent, mut blast, mut sprite) in blasts.iter_mut() {
+        if blast.0.tick(time.delta()).finished() {
+            commands.entity(ent).despawn();
+        } else {
+            sprite.color.set_a(0.8 * (1.0 - blast.0.percent()));
+        }
+    }
+}
@@ -3,24 +3,50 @@ use std::{
     time::Duration,
 };
 
-use bevy::prelude::*;
+use anyhow::ensure;
+use bevy::{prelude::*, reflect::TypeUuid};
 use heron::{
-    rapier_plugin::{convert::IntoRapier, rapier2d::prelude::RigidBodySet, RigidBodyHandle},
-    CollisionEvent, CollisionShape, RigidBody, RotationConstraints, Velocity,
+    rapier_plugin::{
+        convert::IntoRapier, rapier2d::prelude::RigidBodySet, PhysicsWorld, RigidBodyHandle,
+    },
+    CollisionEvent, CollisionLayers, CollisionShape, RigidBody, RotationConstraints, Velocity,
+};
+use rand::Rng;
+use serde::Deserialize;
+
+use crate::{
+    health::DamageEvent,
+    utils::{despawn_all, RonAsset, RonLoader},
+    GameLayers, GameState,
 };
 
 pub struct GunPlugin;
 
 impl Plugin for GunPlugin {
     fn build(&self, app: &mut App) {
-        app.add_system(enable_bullet_ccd)
-            .add_system(despawn_on_collision);
+        app.add_asset::<WeaponDef>()
+            .add_asset_loader(RonLoader::<WeaponDef>::new("weapon.ron"))
+            .add_system(sync_weapon_sprites)
+            .add_system(fade_tracers)
+            .add_system(enable_bullet_ccd)
+            .add_system(despawn_on_collision)
+            .add_system(bullet_damage)
+            // Shots still in the air stay behind when the loop starts over otherwise
+            .add_system_set(
+                SystemSet::on_exit(GameState::Playing)
+                    .with_system(despawn_all::<BulletStats>)
+                    .with_system(despawn_all::<Tracer>),
+            );
     }
 }
 
 #[derive(Component, Debug, Default)]
 pub struct BulletStats {
-    _damage: f32,
+    pub damage: f32,
+    /// Speed enemies are shoved away with when hit
+    pub knockback: f32,
+    /// Enemies left to pass through before the bullet is used up
+    pub pierce: u32,
 }
 
 #[derive(Bundle, Default)]
@@ -31,76 +57,244 @@ pub struct BulletBundle {
     rb: RigidBody,
     constraints: RotationConstraints,
     collision_shape: CollisionShape,
+    collision_layers: CollisionLayers,
     velocity: Velocity,
 }
 
-#[derive(Debug, Component, Clone, Copy)]
-pub enum GunType {
-    Shotgun,
+/// Everything about a gun, loaded from `assets/weapons/<name>.weapon.ron`
+#[derive(Debug, Clone, Deserialize, TypeUuid)]
+#[uuid = "3c8a71d2-5e0f-4b6a-9d43-e1f27a6c5b08"]
+pub struct WeaponDef {
+    /// Seconds between shots
+    pub cooldown: f32,
+    pub shot: ShotKind,
+    /// Per bullet
+    pub damage: f32,
+    pub knockback: f32,
+    /// Enemies each bullet passes through before it's used up
+    #[serde(default)]
+    pub pierce: u32,
+    pub pattern: FiringPattern,
+    /// Shots before reloading
+    pub magazine_size: u32,
+    /// Seconds a reload takes
+    pub reload_time: f32,
+    /// Ammo a new gun comes with besides its full magazine
+    pub reserve_ammo: u32,
+    pub sprite: String,
+    pub bullet_sprite: String,
+    /// Width and height of the bullet's collider
+    pub bullet_size: (f32, f32),
+    pub sfx: String,
 }
 
-impl Default for GunType {
-    fn default() -> Self {
-        Self::Shotgun
+/// How a shot gets to its target
+#[derive(Debug, Clone, Copy, Deserialize)]
+pub enum ShotKind {
+    /// Bullets that fly at `speed` pixels per second
+    Projectile { speed: f32 },
+    /// Instantly hits the first thing along each pellet's path, up to `range` pixels away
+    Hitscan { range: f32 },
+}
+
+/// The streak left by a hitscan shot
+#[derive(Component)]
+struct Tracer(Timer);
+
+/// How the bullets of a single shot leave the barrel
+#[derive(Debug, Clone, Copy, Deserialize)]
+pub struct FiringPattern {
+    /// Bullets per shot
+    pub pellets: u32,
+    /// Angle in radians of the cone the pellets are fanned out across
+    #[serde(default)]
+    pub spread: f32,
+    /// Each pellet's speed varies by up to this fraction of the gun's velocity
+    #[serde(default)]
+    pub velocity_jitter: f32,
+}
+
+impl FiringPattern {
+    /// Direction and speed of each pellet, fanned evenly across the cone
+    /// with a little randomness so shots don't all look the same.
+    /// A single pellet goes anywhere in the cone.
+    pub fn pellet_velocities(
+        &self,
+        aim_direction: Vec2,
+        velocity: f32,
+        rng: &mut impl Rng,
+    ) -> Vec<Vec2> {
+        let aim_angle = aim_direction.y.atan2(aim_direction.x);
+        let (first, step) = if self.pellets > 1 {
+            (-self.spread / 2.0, self.spread / (self.pellets - 1) as f32)
+        } else {
+            (0.0, self.spread)
+        };
+        (0..self.pellets)
+            .map(|pellet| {
+                let jitter_angle = rng.gen_range(-0.5f32..=0.5) * step;
+                let angle = aim_angle + first + pellet as f32 * step + jitter_angle;
+                let speed =
+                    velocity * (1.0 + rng.gen_range(-self.velocity_jitter..=self.velocity_jitter));
+                Vec2::new(angle.cos(), angle.sin()) * speed
+            })
+            .collect()
     }
 }
 
-impl GunType {
+/// Rounds left in a gun
+#[derive(Debug, Clone, Copy)]
+pub struct Ammo {
+    pub magazine: u32,
+    pub reserve: u32,
+}
+
+impl Ammo {
+    pub fn can_reload(&self, magazine_size: u32) -> bool {
+        self.magazine < magazine_size && self.reserve > 0
+    }
+
+    /// Refills the magazine from the reserve
+    pub fn reload(&mut self, magazine_size: u32) {
+        let rounds = magazine_size
+            .saturating_sub(self.magazine)
+            .min(self.reserve);
+        self.magazine += rounds;
+        self.reserve -= rounds;
+    }
+}
+
+/// Running while the gun is being reloaded
+#[derive(Component, Default)]
+pub struct ReloadTimer(pub Option<Timer>);
+
+impl RonAsset for WeaponDef {
+    /// Rejects values that would panic or leave the gun useless
+    fn validate(&self) -> anyhow::Result<()> {
+        ensure!(self.cooldown >= 0.0, "cooldown can't be negative");
+        ensure!(self.reload_time >= 0.0, "reload_time can't be negative");
+        ensure!(
+            self.pattern.velocity_jitter >= 0.0,
+            "velocity_jitter can't be negative"
+        );
+        ensure!(self.pattern.pellets >= 1, "a shot needs at least 1 pellet");
+        ensure!(
+            self.magazine_size >= 1,
+            "magazine_size needs to be at least 1"
+        );
+        Ok(())
+    }
+}
+
+impl WeaponDef {
     pub fn cooldown(&self) -> Duration {
-        match self {
-            GunType::Shotgun => Duration::from_millis(700),
-        }
+        Duration::from_secs_f32(self.cooldown)
     }
 
-    pub fn velocity(&self) -> f32 {
-        match self {
-            GunType::Shotgun => 1000.0,
+    /// What a new gun comes loaded with
+    pub fn full_ammo(&self) -> Ammo {
+        Ammo {
+            magazine: self.magazine_size,
+            reserve: self.reserve_ammo,
         }
     }
 
-    pub fn create_bundle(&self, asset_server: &AssetServer) -> GunBundle {
-        let transform = Transform::from_xyz(10.0, 0.0, 1.1);
-        match self {
-            GunType::Shotgun => GunBundle {
-                gun_type: *self,
-                sprite: SpriteBundle {
-                    texture: asset_server.load("images/shotgun.png"),
-                    transform,
-                    ..Default::default()
-                },
-                gun_timer: GunTimer::default(),
-            },
+    /// Fires a single shot, following the gun's [`ShotKind`] and [`FiringPattern`]
+    pub fn fire(
+        &self,
+        commands: &mut Commands,
+        asset_server: &AssetServer,
+        physics_world: &PhysicsWorld,
+        damage_events: &mut EventWriter<DamageEvent>,
+        shooter: Entity,
+        origin: Vec3,
+        aim_direction: Vec2,
+    ) {
+        // Hitscan shots only need each pellet's direction
+        let speed = match self.shot {
+            ShotKind::Projectile { speed } => speed,
+            ShotKind::Hitscan { .. } => 1.0,
+        };
+        let mut rng = rand::thread_rng();
+        let velocities = self
+            .pattern
+            .pellet_velocities(aim_direction, speed, &mut rng);
+        match self.shot {
+            ShotKind::Projectile { .. } => {
+                commands.spawn_batch(
+                    velocities
+                        .into_iter()
+                        .map(|velocity| self.create_bullet_bundle(asset_server, origin, velocity))
+                        .collect::<Vec<_>>(),
+                );
+            }
+            ShotKind::Hitscan { range } => {
+                for velocity in velocities {
+                    let direction = velocity.normalize_or_zero();
+                    let hit = physics_world.ray_cast_with_filter(
+                        origin,
+                        direction.extend(0.0) * range,
+                        true,
+                        CollisionLayers::none()
+                            .with_group(GameLayers::Bullets)
+                            .with_masks(&[GameLayers::World, GameLayers::Enemies]),
+                        |_| true,
+                    );
+                    let end = hit.map_or(origin + direction.extend(0.0) * range, |hit| {
+                        // Walls have no health, so only enemies take this
+                        damage_events.send(DamageEvent {
+                            source: shooter,
+                            target: hit.entity,
+                            amount: self.damage,
+                            knockback: direction * self.knockback,
+                        });
+                        hit.collision_point
+                    });
+                    spawn_tracer(commands, origin, end);
+                }
+            }
         }
     }
 
-    pub fn create_bullet_bundle(
+    fn create_bullet_bundle(
         &self,
         asset_server: &AssetServer,
         origin: Vec3,
-        aim_direction: Vec2,
+        velocity: Vec2,
     ) -> BulletBundle {
-        let aim_direction = aim_direction.extend(0.0);
+        let direction = velocity.normalize_or_zero().extend(0.0);
         let transform = Transform {
-            translation: origin + aim_direction * 12.0 + Vec3::Z * 1.2,
-            rotation: Quat::from_axis_angle(Vec3::Z, aim_direction.y.atan2(aim_direction.x)),
+            translation: origin + direction * 12.0 + Vec3::Z * 1.2,
+            rotation: Quat::from_axis_angle(Vec3::Z, velocity.y.atan2(velocity.x)),
             ..Default::default()
         };
-        match self {
-            GunType::Shotgun => BulletBundle {
-                bullet_stats: BulletStats { _damage: 3.0 },
-                sprite: SpriteBundle {
-                    texture: asset_server.load("images/shotgun_bullet.png"),
-                    transform,
-                    ..Default::default()
-                },
-                rb: RigidBody::Dynamic,
-                constraints: RotationConstraints::lock(),
-                collision_shape: CollisionShape::Cuboid {
-                    half_extends: Vec3::new(8f32, 1f32, 0f32),
-                    border_radius: None,
-                },
-                velocity: Velocity::from_linear(aim_direction * self.velocity()),
+        let (width, height) = self.bullet_size;
+        BulletBundle {
+            bullet_stats: BulletStats {
+                damage: self.damage,
+                knockback: self.knockback,
+                pierce: self.pierce,
             },
+            sprite: SpriteBundle {
+                texture: asset_server.load(self.bullet_sprite.as_str()),
+                transform,
+                ..Default::default()
+            },
+            // Piercing bullets mustn't bounce off what they pass through
+            rb: if self.pierce > 0 {
+                RigidBody::Sensor
+            } else {
+                RigidBody::Dynamic
+            },
+            constraints: RotationConstraints::lock(),
+            collision_shape: CollisionShape::Cuboid {
+                half_extends: Vec3::new(width / 2.0, height / 2.0, 0.0),
+                border_radius: None,
+            },
+            collision_layers: CollisionLayers::none()
+                .with_group(GameLayers::Bullets)
+                .with_masks(&[GameLayers::World, GameLayers::Enemies]),
+            velocity: Velocity::from_linear(velocity.extend(0.0)),
         }
     }
 
@@ -110,11 +304,95 @@ impl GunType {
         channel: &bevy_kira_audio::AudioChannel,
         asset_server: &AssetServer,
     ) {
-        match self {
-            GunType::Shotgun => {
-                audio.play_in_channel(asset_server.load("sfx/shotgun.wav"), channel)
-            }
-        };
+        audio.play_in_channel(asset_server.load(self.sfx.as_str()), channel);
+    }
+}
+
+/// Which gun something is, by the name of its file in `assets/weapons`
+#[derive(Debug, Component, Clone)]
+pub struct GunType {
+    name: String,
+    /// Weak until [`GunType::load`], then keeps the definition loaded for as long as the gun
+    /// is around, in hand or not
+    def: Handle<WeaponDef>,
+}
+
+impl PartialEq for GunType {
+    fn eq(&self, other: &Self) -> bool {
+        self.name == other.name
+    }
+}
+
+impl Eq for GunType {}
+
+impl Default for GunType {
+    fn default() -> Self {
+        Self::new("shotgun")
+    }
+}
+
+impl GunType {
+    pub fn new(name: &str) -> Self {
+        let path = Self::asset_path(name);
+        Self {
+            name: name.to_string(),
+            def: Handle::weak(path.as_str().into()),
+        }
+    }
+
+    fn asset_path(name: &str) -> String {
+        format!("weapons/{name}.weapon.ron")
+    }
+
+    /// Starts loading the definition if it isn't already
+    pub fn load(&mut self, asset_server: &AssetServer) {
+        if self.def.is_weak() {
+            self.def = asset_server.load(Self::asset_path(&self.name).as_str());
+        }
+    }
+
+    /// `None` until the definition has loaded
+    pub fn def<'a>(&self, weapons: &'a Assets<WeaponDef>) -> Option<&'a WeaponDef> {
+        weapons.get(&self.def)
+    }
+
+    /// The gun as held by a player. Its sprite is filled in by [`sync_weapon_sprites`].
+    pub fn create_bundle(&self, asset_server: &AssetServer) -> GunBundle {
+        let mut gun_type = self.clone();
+        gun_type.load(asset_server);
+        GunBundle {
+            gun_type,
+            sprite: SpriteBundle {
+                transform: Transform::from_xyz(10.0, 0.0, 1.1),
+                ..Default::default()
+            },
+            gun_timer: GunTimer::default(),
+            reload_timer: ReloadTimer::default(),
+        }
+    }
+}
+
+/// Gives guns the sprite from their definition once it has loaded, and again
+/// whenever it is edited
+pub fn sync_weapon_sprites(
+    asset_server: Res<AssetServer>,
+    weapons: Res<Assets<WeaponDef>>,
+    mut events: EventReader<AssetEvent<WeaponDef>>,
+    mut guns: Query<(&GunType, &mut Handle<Image>)>,
+    new_guns: Query<(), Changed<GunType>>,
+) {
+    let changed = events
+        .iter()
+        .filter(|ev| !matches!(ev, AssetEvent::Removed { .. }))
+        .count()
+        > 0;
+    if !changed && new_guns.is_empty() {
+        return;
+    }
+    for (gun_type, mut texture) in guns.iter_mut() {
+        if let Some(def) = gun_type.def(&weapons) {
+            *texture = asset_server.load(def.sprite.as_str());
+        }
     }
 }
 
@@ -142,12 +420,81 @@ fn despawn_on_collision(mut commands: Commands, mut events: EventReader<Collisio
     });
 }
 
+fn spawn_tracer(commands: &mut Commands, from: Vec3, to: Vec3) {
+    let ray = (to - from).truncate();
+    commands
+        .spawn_bundle(SpriteBundle {
+            sprite: Sprite {
+                color: Color::rgb(0.9, 0.9, 0.5),
+                custom_size: Some(Vec2::new(ray.length(), 2.0)),
+                ..Default::default()
+            },
+            transform: Transform {
+                translation: ((from + to) / 2.0).truncate().extend(1.2),
+                rotation: Quat::from_axis_angle(Vec3::Z, ray.y.atan2(ray.x)),
+                ..Default::default()
+            },
+            ..Default::default()
+        })
+        .insert(Tracer(Timer::from_seconds(0.1, false)));
+}
+
+fn fade_tracers(
+    mut commands: Commands,
+    time: Res<Time>,
+    mut tracers: Query<(Entity, &mut Tracer, &mut Sprite)>,
+) {
+    for (ent, mut tracer, mut sprite) in tracers.iter_mut() {
+        if tracer.0.tick(time.delta()).finished() {
+            commands.entity(ent).despawn();
+        } else {
+            sprite.color.set_a(1.0 - tracer.0.percent());
+        }
+    }
+}
+
+/// Bullets hurt the enemies they hit, and are used up unless they can pierce
+fn bullet_damage(
+    mut commands: Commands,
+    mut events: EventReader<CollisionEvent>,
+    mut damage_events: EventWriter<DamageEvent>,
+    mut bullets: Query<(&mut BulletStats, &Velocity)>,
+) {
+    events.iter().filter(|e| e.is_started()).for_each(|ev| {
+        let (e1, e2) = ev.rigid_body_entities();
+        let (l1, l2) = ev.collision_layers();
+        use crate::GameLayers::*;
+        let (bullet, enemy) = if l1.contains_group(Bullets) && l2.contains_group(Enemies) {
+            (e1, e2)
+        } else if l1.contains_group(Enemies) && l2.contains_group(Bullets) {
+            (e2, e1)
+        } else {
+            return;
+        };
+
+        if let Ok((mut stats, velocity)) = bullets.get_mut(bullet) {
+            damage_events.send(DamageEvent {
+                source: bullet,
+                target: enemy,
+                amount: stats.damage,
+                knockback: velocity.linear.truncate().normalize_or_zero() * stats.knockback,
+            });
+            if stats.pierce > 0 {
+                stats.pierce -= 1;
+            } else {
+                commands.entity(bullet).despawn();
+            }
+        }
+    });
+}
+
 #[derive(Bundle, Default)]
 pub struct GunBundle {
     gun_type: GunType,
     #[bundle]
     sprite: SpriteBundle,
     gun_timer: GunTimer,
+    reload_timer: ReloadTimer,
 }
 
 #[derive(Component)]
@@ -172,3 +519,75 @@ impl Default for GunTimer {
         Self(Timer::new(Duration::from_millis(1), false)) // 0 causes a panic
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn weapon(source: &str) -> WeaponDef {
+        ron::de::from_str(source).unwrap()
+    }
+
+    #[test]
+    fn bundled_weapons_are_valid() {
+        for source in [
+            include_str!("../assets/weapons/crossbow.weapon.ron"),
+            include_str!("../assets/weapons/pistol.weapon.ron"),
+            include_str!("../assets/weapons/rifle.weapon.ron"),
+            include_str!("../assets/weapons/shotgun.weapon.ron"),
+            include_str!("../assets/weapons/smg.weapon.ron"),
+        ] {
+            weapon(source).validate().unwrap();
+        }
+    }
+
+    #[test]
+    fn rejects_values_that_would_panic_or_fire_nothing() {
+        let shotgun = weapon(include_str!("../assets/weapons/shotgun.weapon.ron"));
+        let broken: [fn(&mut WeaponDef); 5] = [
+            |def| def.cooldown = -0.1,
+            |def| def.reload_time = -1.0,
+            |def| def.pattern.velocity_jitter = -0.2,
+            |def| def.pattern.pellets = 0,
+            |def| def.magazine_size = 0,
+        ];
+        for (i, break_def) in broken.iter().enumerate() {
+            let mut def = shotgun.clone();
+            break_def(&mut def);
+            assert!(def.validate().is_err(), "case {i}");
+        }
+    }
+
+    #[test]
+    fn reloading_tops_up_the_magazine_from_the_reserve() {
+        let mut ammo = Ammo {
+            magazine: 2,
+            reserve: 20,
+        };
+        assert!(ammo.can_reload(8));
+        ammo.reload(8);
+        assert_eq!((ammo.magazine, ammo.reserve), (8, 14));
+        assert!(!ammo.can_reload(8));
+        // Reloading a full magazine does nothing
+        ammo.reload(8);
+        assert_eq!((ammo.magazine, ammo.reserve), (8, 14));
+    }
+
+    #[test]
+    fn reloading_takes_what_is_left_of_the_reserve() {
+        let mut ammo = Ammo {
+            magazine: 1,
+            reserve: 3,
+        };
+        ammo.reload(8);
+        assert_eq!((ammo.magazine, ammo.reserve), (4, 0));
+        assert!(!ammo.can_reload(8));
+        // A magazine over the size, e.g. from a gun that was redefined, isn't emptied
+        let mut ammo = Ammo {
+            magazine: 10,
+            reserve: 5,
+        };
+        ammo.reload(8);
+        assert_eq!((ammo.magazine, ammo.reserve), (10, 5));
+    }
+}
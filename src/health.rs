@@ -0,0 +1,195 @@
+use bevy::{prelude::*, utils::HashSet};
+use heron::Velocity;
+
+use crate::{utils::despawn_all, GameState};
+
+/// How long a hit stops something from moving on its own
+const KNOCKBACK_SECS: f32 = 0.15;
+const DAMAGE_NUMBER_SECS: f32 = 0.6;
+
+pub struct HealthPlugin;
+
+impl Plugin for HealthPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_event::<DamageEvent>()
+            .add_system_set(
+                SystemSet::on_update(GameState::Playing)
+                    .with_system(apply_damage)
+                    .with_system(tick_invulnerability)
+                    .with_system(tick_knockback)
+                    .with_system(animate_damage_numbers),
+            )
+            .add_system_set(
+                SystemSet::on_exit(GameState::Playing).with_system(despawn_all::<DamageNumber>),
+            );
+    }
+}
+
+#[derive(Component, Clone)]
+pub struct Health {
+    pub current: f32,
+    pub max: f32,
+}
+
+impl Health {
+    pub fn new(max: f32) -> Self {
+        Self { current: max, max }
+    }
+
+    pub fn is_dead(&self) -> bool {
+        self.current <= 0.0
+    }
+}
+
+/// Everything that hurts goes through this, whether it's a bullet, an explosion
+/// or an enemy bumping into a player
+pub struct DamageEvent {
+    pub source: Entity,
+    pub target: Entity,
+    pub amount: f32,
+    /// Velocity the target is shoved with
+    pub knockback: Vec2,
+}
+
+/// Makes whatever has it impossible to hurt for this many seconds after each hit
+#[derive(Component)]
+pub struct HitInvulnerability(pub f32);
+
+#[derive(Component)]
+pub struct Invulnerable(Timer);
+
+impl Invulnerable {
+    pub fn new(secs: f32) -> Self {
+        Self(Timer::from_seconds(secs, false))
+    }
+
+    pub fn remaining_secs(&self) -> f32 {
+        self.0.duration().as_secs_f32() - self.0.elapsed_secs()
+    }
+}
+
+/// Movement systems leave things alone while they're being knocked back
+#[derive(Component)]
+pub struct Knockback(Timer);
+
+#[derive(Component)]
+struct DamageNumber(Timer);
+
+fn apply_damage(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    mut events: EventReader<DamageEvent>,
+    mut targets: Query<
+        (
+            &mut Health,
+            &Transform,
+            Option<&mut Velocity>,
+            Option<&HitInvulnerability>,
+        ),
+        Without<Invulnerable>,
+    >,
+) {
+    // Anything that turns invulnerable only takes the first hit this frame
+    let mut invulnerable = HashSet::default();
+    for ev in events.iter() {
+        if invulnerable.contains(&ev.target) {
+            continue;
+        }
+        let Ok((mut health, transform, velocity, hit_invulnerability)) =
+            targets.get_mut(ev.target) else { continue };
+
+        health.current -= ev.amount;
+        debug!(
+            "{:?} hit {:?} for {}, {} left",
+            ev.source, ev.target, ev.amount, health.current
+        );
+        spawn_damage_number(&mut commands, &asset_server, transform, ev.amount);
+
+        if let Some(HitInvulnerability(secs)) = hit_invulnerability {
+            invulnerable.insert(ev.target);
+            commands.entity(ev.target).insert(Invulnerable::new(*secs));
+        }
+        match velocity {
+            Some(mut velocity) if ev.knockback != Vec2::ZERO => {
+                velocity.linear = ev.knockback.extend(0.0);
+                commands
+                    .entity(ev.target)
+                    .insert(Knockback(Timer::from_seconds(KNOCKBACK_SECS, false)));
+            }
+            _ => {}
+        }
+    }
+}
+
+fn spawn_damage_number(
+    commands: &mut Commands,
+    asset_server: &AssetServer,
+    transform: &Transform,
+    amount: f32,
+) {
+    commands
+        .spawn_bundle(Text2dBundle {
+            text: Text::with_section(
+                format!("{amount}"),
+                TextStyle {
+                    font: asset_server.load("fonts/FiraSans-Bold.ttf"),
+                    font_size: 20.0,
+                    color: Color::WHITE,
+                },
+                TextAlignment {
+                    horizontal: HorizontalAlign::Center,
+                    vertical: VerticalAlign::Center,
+                },
+            ),
+            transform: Transform::from_translation(
+                transform.translation.truncate().extend(5.0) + Vec3::Y * 16.0,
+            ),
+            ..Default::default()
+        })
+        .insert(DamageNumber(Timer::from_seconds(DAMAGE_NUMBER_SECS, false)));
+}
+
+fn tick_invulnerability(
+    mut commands: Commands,
+    time: Res<Time>,
+    mut query: Query<(Entity, &mut Invulnerable, &mut TextureAtlasSprite)>,
+) {
+    for (ent, mut invulnerable, mut sprite) in query.iter_mut() {
+        invulnerable.0.tick(time.delta());
+        if invulnerable.0.finished() {
+            sprite.color.set_a(1.0);
+            commands.entity(ent).remove::<Invulnerable>();
+        } else {
+            // Flicker so it's clear it can't be hurt right now
+            let visible = (invulnerable.0.elapsed_secs() * 10.0) as u32 % 2 == 0;
+            sprite.color.set_a(if visible { 1.0 } else { 0.3 });
+        }
+    }
+}
+
+fn tick_knockback(
+    mut commands: Commands,
+    time: Res<Time>,
+    mut query: Query<(Entity, &mut Knockback)>,
+) {
+    for (ent, mut knockback) in query.iter_mut() {
+        if knockback.0.tick(time.delta()).finished() {
+            commands.entity(ent).remove::<Knockback>();
+        }
+    }
+}
+
+fn animate_damage_numbers(
+    mut commands: Commands,
+    time: Res<Time>,
+    mut query: Query<(Entity, &mut DamageNumber, &mut Transform, &mut Text)>,
+) {
+    for (ent, mut number, mut transform, mut text) in query.iter_mut() {
+        if number.0.tick(time.delta()).finished() {
+            commands.entity(ent).despawn();
+            continue;
+        }
+        transform.translation.y += 40.0 * time.delta_seconds();
+        text.sections[0].style.color.set_a(1.0 - number.0.percent());
+    }
+}
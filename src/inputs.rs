@@ -1,16 +1,33 @@
-use bevy::{core::FixedTimestep, prelude::*};
+use bevy::{
+    core::FixedTimestep, input::mouse::MouseWheel, prelude::*, render::camera::CameraProjection,
+};
+
+use crate::{enemy::EnemyStats, levels::MainCamera, player::ControlledPlayer};
+
+pub mod bindings;
+
+use self::bindings::{Action, ActionMap, Devices};
 
 pub struct GameInputPlugin;
 
 impl Plugin for GameInputPlugin {
     fn build(&self, app: &mut App) {
         app.insert_resource(PlayerInput::default())
-            .add_system_to_stage(
+            .insert_resource(ActionMap::load())
+            .init_resource::<AimAssist>()
+            .add_system_set_to_stage(
                 CoreStage::PreUpdate,
-                get_player_inputs.with_run_criteria(
-                    FixedTimestep::steps_per_second(60.0).with_label("update_inputs"),
-                ),
-            );
+                SystemSet::new()
+                    .with_run_criteria(
+                        FixedTimestep::steps_per_second(60.0).with_label("update_inputs"),
+                    )
+                    .with_system(get_player_inputs)
+                    .with_system(get_player_aim),
+            )
+            // Presses have to be seen exactly once, so these go every frame rather than with
+            // the fixed step, which can run several times or not at all in a frame
+            .add_system_to_stage(CoreStage::PreUpdate, get_button_inputs)
+            .add_system_to_stage(CoreStage::PreUpdate, toggle_aim_assist);
     }
 }
 
@@ -21,6 +38,12 @@ pub struct PlayerInput {
     pub shoot: ButtonState,
     pub throw: ButtonState,
     pub dodge: ButtonState,
+    pub reload: ButtonState,
+    /// Inventory slot picked with its slot binding
+    pub select_slot: Option<usize>,
+    /// Inventory slots to move the selection along by, from the scroll wheel or slot bindings.
+    /// Only set on the frame of the press, so it's recorded and acted on once.
+    pub cycle_slot: i32,
 }
 
 #[derive(Debug, Clone, Copy, PartialEq)]
@@ -73,65 +96,254 @@ impl ButtonState {
     }
 }
 
+/// Stick tilt, out of 1, below which it counts as centered
+const MOVE_DEADZONE: f32 = 0.2;
+const AIM_DEADZONE: f32 = 0.3;
+
+/// How far away, in pixels, aim assist picks out enemies
+const ASSIST_RANGE: f32 = 400.0;
+/// Widest angle, in radians, between the stick and an enemy aim assist snaps to
+const ASSIST_ANGLE: f32 = 0.3;
+
+/// Scales the stick so it goes from 0 at the edge of the deadzone to 1 at full tilt
+fn apply_deadzone(stick: Vec2, deadzone: f32) -> Vec2 {
+    let tilt = stick.length();
+    if tilt < deadzone {
+        Vec2::ZERO
+    } else {
+        stick / tilt * ((tilt - deadzone) / (1.0 - deadzone)).min(1.0)
+    }
+}
+
+fn stick_position(
+    gamepad_axes: &Axis<GamepadAxis>,
+    gamepad: Option<Gamepad>,
+    x: GamepadAxisType,
+    y: GamepadAxisType,
+) -> Vec2 {
+    gamepad.map_or(Vec2::ZERO, |gamepad| {
+        let axis = |axis_type| {
+            gamepad_axes
+                .get(GamepadAxis(gamepad, axis_type))
+                .unwrap_or(0.0)
+        };
+        Vec2::new(axis(x), axis(y))
+    })
+}
+
+/// Where the cursor points in the world, as seen through the main camera
+fn cursor_to_world(
+    window: &Window,
+    cursor: Vec2,
+    camera_transform: &GlobalTransform,
+    projection: &OrthographicProjection,
+) -> Vec2 {
+    let window_size = Vec2::new(window.width(), window.height());
+    let ndc = cursor / window_size * 2.0 - Vec2::ONE;
+    let ndc_to_world =
+        camera_transform.compute_matrix() * projection.get_projection_matrix().inverse();
+    ndc_to_world.project_point3(ndc.extend(0.0)).truncate()
+}
+
 fn get_player_inputs(
+    action_map: Res<ActionMap>,
     keys: Res<Input<KeyCode>>,
     mouse: Res<Input<MouseButton>>,
-    windows: Res<Windows>,
+    gamepads: Res<Gamepads>,
+    gamepad_buttons: Res<Input<GamepadButton>>,
+    gamepad_axes: Res<Axis<GamepadAxis>>,
     mut player_input: ResMut<PlayerInput>,
 ) {
-    // Create our move vector from keyboard inputs
-    let mut move_direction = Vec2::ZERO;
-    move_direction.x -= if keys.pressed(KeyCode::A) || keys.pressed(KeyCode::Left) {
-        1.0
-    } else {
-        0.0
+    let devices = Devices {
+        keys: &keys,
+        mouse: &mouse,
+        gamepad_buttons: &gamepad_buttons,
+        gamepad: gamepads.iter().next().copied(),
     };
-    move_direction.x += if keys.pressed(KeyCode::D) || keys.pressed(KeyCode::Right) {
-        1.0
+    let pressed = |action| action_map.pressed(action, &devices);
+
+    // Create our move vector from the movement bindings
+    let mut move_direction = Vec2::ZERO;
+    move_direction.x -= if pressed(Action::MoveLeft) { 1.0 } else { 0.0 };
+    move_direction.x += if pressed(Action::MoveRight) { 1.0 } else { 0.0 };
+    move_direction.y += if pressed(Action::MoveUp) { 1.0 } else { 0.0 };
+    move_direction.y -= if pressed(Action::MoveDown) { 1.0 } else { 0.0 };
+    if move_direction.length_squared() != 0.0 {
+        move_direction = move_direction.normalize();
     } else {
-        0.0
+        // Sticks can walk slower than full speed
+        move_direction = apply_deadzone(
+            stick_position(
+                &gamepad_axes,
+                devices.gamepad,
+                GamepadAxisType::LeftStickX,
+                GamepadAxisType::LeftStickY,
+            ),
+            MOVE_DEADZONE,
+        );
+    }
+    player_input.move_direction = move_direction;
+}
+
+fn get_button_inputs(
+    action_map: Res<ActionMap>,
+    keys: Res<Input<KeyCode>>,
+    mouse: Res<Input<MouseButton>>,
+    mut scroll: EventReader<MouseWheel>,
+    gamepads: Res<Gamepads>,
+    gamepad_buttons: Res<Input<GamepadButton>>,
+    mut player_input: ResMut<PlayerInput>,
+) {
+    let devices = Devices {
+        keys: &keys,
+        mouse: &mouse,
+        gamepad_buttons: &gamepad_buttons,
+        gamepad: gamepads.iter().next().copied(),
     };
-    move_direction.y += if keys.pressed(KeyCode::W) || keys.pressed(KeyCode::Up) {
-        1.0
+    let pressed = |action| action_map.pressed(action, &devices);
+
+    // Get our action states
+    let input = &mut *player_input;
+    for (action, state) in [
+        (Action::Dodge, &mut input.dodge),
+        (Action::Shoot, &mut input.shoot),
+        (Action::Throw, &mut input.throw),
+        (Action::Reload, &mut input.reload),
+    ] {
+        if pressed(action) {
+            state.downgrade()
+        } else {
+            state.upgrade()
+        };
+    }
+    // Inventory slots
+    player_input.select_slot = Action::SLOTS.iter().position(|&action| pressed(action));
+    let scrolled = scroll.iter().map(|ev| ev.y).sum::<f32>();
+    let zooming = pressed(Action::Zoom);
+    // Scrolling down moves to the next slot
+    player_input.cycle_slot = if action_map.just_pressed(Action::NextSlot, &devices) {
+        1
+    } else if action_map.just_pressed(Action::PreviousSlot, &devices) {
+        -1
+    } else if zooming || scrolled == 0.0 {
+        0
+    } else if scrolled < 0.0 {
+        1
     } else {
-        0.0
+        -1
     };
-    move_direction.y -= if keys.pressed(KeyCode::S) || keys.pressed(KeyCode::Down) {
-        1.0
-    } else {
-        0.0
+}
+
+#[derive(Default)]
+struct AimAssist {
+    enabled: bool,
+    /// The enemy aimed at while the stick is let go
+    target: Option<Entity>,
+}
+
+fn toggle_aim_assist(
+    action_map: Res<ActionMap>,
+    keys: Res<Input<KeyCode>>,
+    mouse: Res<Input<MouseButton>>,
+    gamepads: Res<Gamepads>,
+    gamepad_buttons: Res<Input<GamepadButton>>,
+    mut aim_assist: ResMut<AimAssist>,
+) {
+    let devices = Devices {
+        keys: &keys,
+        mouse: &mouse,
+        gamepad_buttons: &gamepad_buttons,
+        gamepad: gamepads.iter().next().copied(),
     };
-    if move_direction.length_squared() != 0.0 {
-        move_direction = move_direction.normalize();
+    if action_map.just_pressed(Action::AimAssist, &devices) {
+        aim_assist.enabled = !aim_assist.enabled;
+        aim_assist.target = None;
+        info!(
+            "Aim assist {}",
+            if aim_assist.enabled { "on" } else { "off" }
+        );
     }
-    player_input.move_direction = move_direction;
+}
 
-    // Create our aim vector
+fn get_player_aim(
+    gamepads: Res<Gamepads>,
+    gamepad_axes: Res<Axis<GamepadAxis>>,
+    windows: Res<Windows>,
+    cameras: Query<(&GlobalTransform, &OrthographicProjection), With<MainCamera>>,
+    players: Query<&Transform, With<ControlledPlayer>>,
+    enemies: Query<(Entity, &Transform), With<EnemyStats>>,
+    mut player_input: ResMut<PlayerInput>,
+    mut aim_assist: ResMut<AimAssist>,
+    // Whether the right stick rather than the mouse was used to aim last
+    mut stick_aiming: Local<bool>,
+    // Where the cursor was in the window on the last step
+    mut last_cursor: Local<Option<Vec2>>,
+) {
+    // Cursor events can come and go between fixed steps, so look at where it is instead
     let window = windows.get_primary().unwrap();
+    let cursor = window.cursor_position();
+    let cursor_moved = cursor != *last_cursor;
+    *last_cursor = cursor;
+
+    // Aim is relative to where the player is, which needn't be the middle of the screen
+    let Ok(player) = players.get_single() else { return };
+    let player_position = player.translation.truncate();
+
+    let aim_stick = apply_deadzone(
+        stick_position(
+            &gamepad_axes,
+            gamepads.iter().next().copied(),
+            GamepadAxisType::RightStickX,
+            GamepadAxisType::RightStickY,
+        ),
+        AIM_DEADZONE,
+    );
+    if aim_stick != Vec2::ZERO {
+        *stick_aiming = true;
+        player_input.aim_direction = aim_stick;
+    } else if cursor_moved {
+        *stick_aiming = false;
+        aim_assist.target = None;
+    }
+
+    if *stick_aiming && aim_assist.enabled {
+        if aim_stick != Vec2::ZERO {
+            // Lock onto the enemy closest to where the stick points
+            aim_assist.target = enemies
+                .iter()
+                .filter_map(|(ent, transform)| {
+                    let offset = transform.translation.truncate() - player_position;
+                    let angle = aim_stick.angle_between(offset).abs();
+                    (offset.length() < ASSIST_RANGE && angle < ASSIST_ANGLE).then(|| (ent, angle))
+                })
+                .min_by(|(_, a), (_, b)| a.partial_cmp(b).unwrap())
+                .map(|(ent, _)| ent);
+        }
+        // Keep tracking the target after the stick is let go, until it dies or gets away
+        if let Some(target) = aim_assist.target {
+            match enemies.get(target) {
+                Ok((_, transform)) => {
+                    let offset = transform.translation.truncate() - player_position;
+                    if offset.length() < ASSIST_RANGE {
+                        player_input.aim_direction = offset;
+                    } else {
+                        aim_assist.target = None;
+                    }
+                }
+                Err(_) => aim_assist.target = None,
+            }
+        }
+    }
 
-    if let Some(position) = window.cursor_position() {
-        player_input.aim_direction =
-            position - Vec2::new(window.width() / 2.0, window.height() / 2.0);
+    // The cursor stays wherever it was left, so it only aims while the stick isn't
+    if !*stick_aiming {
+        if let (Some(cursor), Ok((camera_transform, projection))) = (cursor, cameras.get_single()) {
+            let cursor_position = cursor_to_world(window, cursor, camera_transform, projection);
+            player_input.aim_direction = cursor_position - player_position;
+        }
     }
 
     if player_input.aim_direction.length_squared() != 0.0 {
         player_input.aim_direction = player_input.aim_direction.normalize();
     }
-
-    // Get our action states
-    if keys.pressed(KeyCode::Space) {
-        player_input.dodge.downgrade()
-    } else {
-        player_input.dodge.upgrade()
-    };
-    if mouse.pressed(MouseButton::Left) {
-        player_input.shoot.downgrade()
-    } else {
-        player_input.shoot.upgrade()
-    };
-    if mouse.pressed(MouseButton::Right) {
-        player_input.throw.downgrade()
-    } else {
-        player_input.throw.upgrade()
-    };
 }
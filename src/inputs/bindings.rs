@@ -0,0 +1,352 @@
+use std::{collections::BTreeMap, fmt};
+
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+/// Where the controls are kept between runs, relative to the working directory
+#[cfg(not(target_arch = "wasm32"))]
+const BINDINGS_FILE: &str = "bindings.ron";
+
+/// How many bindings each action can have, shown as a primary and secondary one
+pub const BINDING_SLOTS: usize = 2;
+
+/// Something the player can do, independent of what it's bound to
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub enum Action {
+    MoveUp,
+    MoveDown,
+    MoveLeft,
+    MoveRight,
+    Shoot,
+    Throw,
+    Dodge,
+    Reload,
+    NextSlot,
+    PreviousSlot,
+    Slot1,
+    Slot2,
+    Slot3,
+    Slot4,
+    Slot5,
+    Slot6,
+    Slot7,
+    Slot8,
+    Slot9,
+    /// Held to make the scroll wheel zoom instead of switching slots
+    Zoom,
+    Clone,
+    /// Toggles aim assist for the right stick
+    AimAssist,
+}
+
+impl Action {
+    pub const ALL: [Action; 22] = [
+        Action::MoveUp,
+        Action::MoveDown,
+        Action::MoveLeft,
+        Action::MoveRight,
+        Action::Shoot,
+        Action::Throw,
+        Action::Dodge,
+        Action::Reload,
+        Action::NextSlot,
+        Action::PreviousSlot,
+        Action::Slot1,
+        Action::Slot2,
+        Action::Slot3,
+        Action::Slot4,
+        Action::Slot5,
+        Action::Slot6,
+        Action::Slot7,
+        Action::Slot8,
+        Action::Slot9,
+        Action::Zoom,
+        Action::Clone,
+        Action::AimAssist,
+    ];
+
+    /// Picks the inventory slot with the same index
+    pub const SLOTS: [Action; 9] = [
+        Action::Slot1,
+        Action::Slot2,
+        Action::Slot3,
+        Action::Slot4,
+        Action::Slot5,
+        Action::Slot6,
+        Action::Slot7,
+        Action::Slot8,
+        Action::Slot9,
+    ];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            Action::MoveUp => "Move up",
+            Action::MoveDown => "Move down",
+            Action::MoveLeft => "Move left",
+            Action::MoveRight => "Move right",
+            Action::Shoot => "Shoot",
+            Action::Throw => "Throw",
+            Action::Dodge => "Dodge",
+            Action::Reload => "Reload",
+            Action::NextSlot => "Next slot",
+            Action::PreviousSlot => "Previous slot",
+            Action::Slot1 => "Slot 1",
+            Action::Slot2 => "Slot 2",
+            Action::Slot3 => "Slot 3",
+            Action::Slot4 => "Slot 4",
+            Action::Slot5 => "Slot 5",
+            Action::Slot6 => "Slot 6",
+            Action::Slot7 => "Slot 7",
+            Action::Slot8 => "Slot 8",
+            Action::Slot9 => "Slot 9",
+            Action::Zoom => "Zoom",
+            Action::Clone => "Clone",
+            Action::AimAssist => "Aim assist",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Binding {
+    Key(KeyCode),
+    Mouse(MouseButton),
+    /// A button on whichever gamepad is playing
+    Gamepad(GamepadButtonType),
+}
+
+impl Binding {
+    fn pressed(&self, devices: &Devices) -> bool {
+        match *self {
+            Binding::Key(key) => devices.keys.pressed(key),
+            Binding::Mouse(button) => devices.mouse.pressed(button),
+            Binding::Gamepad(button_type) => devices.gamepad.map_or(false, |gamepad| {
+                devices
+                    .gamepad_buttons
+                    .pressed(GamepadButton(gamepad, button_type))
+            }),
+        }
+    }
+
+    fn just_pressed(&self, devices: &Devices) -> bool {
+        match *self {
+            Binding::Key(key) => devices.keys.just_pressed(key),
+            Binding::Mouse(button) => devices.mouse.just_pressed(button),
+            Binding::Gamepad(button_type) => devices.gamepad.map_or(false, |gamepad| {
+                devices
+                    .gamepad_buttons
+                    .just_pressed(GamepadButton(gamepad, button_type))
+            }),
+        }
+    }
+}
+
+impl fmt::Display for Binding {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Binding::Key(key) => write!(f, "{key:?}"),
+            Binding::Mouse(button) => write!(f, "Mouse {button:?}"),
+            Binding::Gamepad(button_type) => write!(f, "Pad {button_type:?}"),
+        }
+    }
+}
+
+/// Everything a binding can be pressed on
+pub struct Devices<'a> {
+    pub keys: &'a Input<KeyCode>,
+    pub mouse: &'a Input<MouseButton>,
+    pub gamepad_buttons: &'a Input<GamepadButton>,
+    /// Only the first gamepad plays
+    pub gamepad: Option<Gamepad>,
+}
+
+/// What each action is bound to, kept in `bindings.ron` between runs
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ActionMap {
+    bindings: BTreeMap<Action, Vec<Binding>>,
+}
+
+impl Default for ActionMap {
+    fn default() -> Self {
+        use Action::*;
+        use Binding::{Gamepad as Pad, Key, Mouse};
+        let bindings = [
+            (MoveUp, vec![Key(KeyCode::W), Key(KeyCode::Up)]),
+            (MoveDown, vec![Key(KeyCode::S), Key(KeyCode::Down)]),
+            (MoveLeft, vec![Key(KeyCode::A), Key(KeyCode::Left)]),
+            (MoveRight, vec![Key(KeyCode::D), Key(KeyCode::Right)]),
+            (
+                Shoot,
+                vec![
+                    Mouse(MouseButton::Left),
+                    Pad(GamepadButtonType::RightTrigger2),
+                ],
+            ),
+            (
+                Throw,
+                vec![
+                    Mouse(MouseButton::Right),
+                    Pad(GamepadButtonType::LeftTrigger2),
+                ],
+            ),
+            (
+                Dodge,
+                vec![Key(KeyCode::Space), Pad(GamepadButtonType::South)],
+            ),
+            (Reload, vec![Key(KeyCode::R), Pad(GamepadButtonType::West)]),
+            (
+                NextSlot,
+                vec![Key(KeyCode::E), Pad(GamepadButtonType::RightTrigger)],
+            ),
+            (
+                PreviousSlot,
+                vec![Key(KeyCode::Q), Pad(GamepadButtonType::LeftTrigger)],
+            ),
+            (Slot1, vec![Key(KeyCode::Key1)]),
+            (Slot2, vec![Key(KeyCode::Key2)]),
+            (Slot3, vec![Key(KeyCode::Key3)]),
+            (Slot4, vec![Key(KeyCode::Key4)]),
+            (Slot5, vec![Key(KeyCode::Key5)]),
+            (Slot6, vec![Key(KeyCode::Key6)]),
+            (Slot7, vec![Key(KeyCode::Key7)]),
+            (Slot8, vec![Key(KeyCode::Key8)]),
+            (Slot9, vec![Key(KeyCode::Key9)]),
+            (Zoom, vec![Key(KeyCode::LControl), Key(KeyCode::RControl)]),
+            (Clone, vec![Key(KeyCode::C), Pad(GamepadButtonType::North)]),
+            (AimAssist, vec![Pad(GamepadButtonType::RightThumb)]),
+        ];
+        Self {
+            bindings: bindings.into_iter().collect(),
+        }
+    }
+}
+
+impl ActionMap {
+    /// Reads the saved controls, falling back to the defaults for anything missing
+    pub fn load() -> Self {
+        let mut map = Self::default();
+        #[cfg(not(target_arch = "wasm32"))]
+        match std::fs::read_to_string(BINDINGS_FILE) {
+            Ok(source) => match ron::de::from_str::<ActionMap>(&source) {
+                Ok(saved) => map.bindings.extend(saved.bindings),
+                Err(e) => warn!("Ignoring invalid {BINDINGS_FILE}: {e}"),
+            },
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+            Err(e) => warn!("Couldn't read {BINDINGS_FILE}: {e}"),
+        }
+        map
+    }
+
+    pub fn save(&self) -> anyhow::Result<()> {
+        #[cfg(not(target_arch = "wasm32"))]
+        {
+            let source = ron::ser::to_string_pretty(self, ron::ser::PrettyConfig::default())?;
+            std::fs::write(BINDINGS_FILE, source)?;
+            info!("Saved controls to {BINDINGS_FILE}");
+        }
+        Ok(())
+    }
+
+    pub fn bindings(&self, action: Action) -> &[Binding] {
+        self.bindings.get(&action).map_or(&[], Vec::as_slice)
+    }
+
+    /// Puts the binding in one of the action's slots, leaving its other binding alone.
+    /// An action that already had the binding gets the one it replaces instead, so no two
+    /// actions share a button. Returns that action, if any.
+    pub fn rebind(&mut self, action: Action, slot: usize, binding: Binding) -> Option<Action> {
+        debug_assert!(slot < BINDING_SLOTS);
+        let own = self.bindings.entry(action).or_default();
+        if let Some(i) = own.iter().position(|&bound| bound == binding) {
+            // Already bound to the other slot, so the two trade places
+            if slot < own.len() {
+                own.swap(i, slot);
+            }
+            return None;
+        }
+        let replaced = own.get(slot).copied();
+        let mut swapped = None;
+        for (&other, bindings) in self.bindings.iter_mut() {
+            if other == action {
+                continue;
+            }
+            let Some(i) = bindings.iter().position(|&bound| bound == binding) else { continue };
+            match replaced {
+                Some(replaced) => bindings[i] = replaced,
+                None => {
+                    bindings.remove(i);
+                }
+            }
+            swapped = Some(other);
+        }
+        let bindings = self.bindings.entry(action).or_default();
+        match bindings.get_mut(slot) {
+            Some(bound) => *bound = binding,
+            None => bindings.push(binding),
+        }
+        swapped
+    }
+
+    pub fn pressed(&self, action: Action, devices: &Devices) -> bool {
+        self.bindings(action)
+            .iter()
+            .any(|binding| binding.pressed(devices))
+    }
+
+    pub fn just_pressed(&self, action: Action, devices: &Devices) -> bool {
+        self.bindings(action)
+            .iter()
+            .any(|binding| binding.just_pressed(devices))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rebinding_a_slot_keeps_the_other_one() {
+        let mut map = ActionMap::default();
+        assert_eq!(
+            map.rebind(Action::MoveUp, 1, Binding::Key(KeyCode::I)),
+            None
+        );
+        assert_eq!(
+            map.bindings(Action::MoveUp),
+            [Binding::Key(KeyCode::W), Binding::Key(KeyCode::I)]
+        );
+        // The gamepad button gets replaced like anything else in its slot
+        map.rebind(Action::Shoot, 1, Binding::Key(KeyCode::F));
+        assert_eq!(
+            map.bindings(Action::Shoot),
+            [Binding::Mouse(MouseButton::Left), Binding::Key(KeyCode::F)]
+        );
+    }
+
+    #[test]
+    fn taking_another_actions_binding_swaps_them() {
+        let mut map = ActionMap::default();
+        let swapped = map.rebind(Action::MoveUp, 0, Binding::Key(KeyCode::S));
+        assert_eq!(swapped, Some(Action::MoveDown));
+        assert_eq!(
+            map.bindings(Action::MoveUp),
+            [Binding::Key(KeyCode::S), Binding::Key(KeyCode::Up)]
+        );
+        assert_eq!(
+            map.bindings(Action::MoveDown),
+            [Binding::Key(KeyCode::W), Binding::Key(KeyCode::Down)]
+        );
+        // An empty slot has nothing to give back, so the other action just loses it
+        map.rebind(Action::Slot1, 1, Binding::Key(KeyCode::Key2));
+        assert_eq!(
+            map.bindings(Action::Slot1),
+            [Binding::Key(KeyCode::Key1), Binding::Key(KeyCode::Key2)]
+        );
+        assert!(map.bindings(Action::Slot2).is_empty());
+        // Binding one of its own buttons to the other slot trades them
+        map.rebind(Action::Slot1, 0, Binding::Key(KeyCode::Key2));
+        assert_eq!(
+            map.bindings(Action::Slot1),
+            [Binding::Key(KeyCode::Key2), Binding::Key(KeyCode::Key1)]
+        );
+    }
+}
@@ -1,12 +1,19 @@
-use std::{
-    mem::replace,
-    ops::{Deref, DerefMut},
-};
+use std::ops::{Deref, DerefMut};
 
 use bevy::prelude::*;
 use heron::{CollisionEvent, CollisionLayers, CollisionShape, RigidBody};
 
-use crate::{gun::GunType, inputs::PlayerInput, player::ControlledPlayer, GameLayers, GameState};
+use crate::{
+    grenade::throw_grenade,
+    gun::{Ammo, GunType, ReloadTimer, WeaponDef},
+    levels::{map::MapInitData, objective::ObjectiveProgress},
+    player::{ControllablePlayer, ControlledPlayer, PlayerInputTick},
+    totem::place_totem,
+    GameLayers, GameState,
+};
+
+/// How many magazines worth of rounds an ammo pickup holds
+const AMMO_PICKUP_MAGAZINES: u32 = 2;
 
 pub struct ItemPlugin;
 
@@ -20,6 +27,8 @@ impl Plugin for ItemPlugin {
         .add_system_set(
             SystemSet::on_update(GameState::Playing)
                 .with_system(update_inventory_ui)
+                .with_system(update_ammo_ui)
+                .with_system(sync_pickup_sprites)
                 .with_system(drop_pickup)
                 .with_system(collide_pickups),
         )
@@ -29,26 +38,63 @@ impl Plugin for ItemPlugin {
 
 #[derive(Clone, Component, Debug)]
 pub enum Item {
-    Gun(GunType),
+    /// The ammo is `None` until the gun is first used, then filled up from its definition
+    Gun(GunType, Option<Ammo>),
     Grenade,
-    _Totem,
+    /// Set down with the throw button to slow the enemies around it, see [`crate::totem`]
+    Totem,
+    /// Spare rounds for whichever gun is held
+    Ammo,
 }
 
 impl Item {
-    fn image_path(&self) -> &'static str {
+    /// A new gun by the name of its definition, see [`GunType`]
+    pub fn gun(name: &str) -> Self {
+        Item::Gun(GunType::new(name), None)
+    }
+
+    /// `None` for guns whose definition hasn't loaded yet
+    fn image_path<'a>(&self, weapons: &'a Assets<WeaponDef>) -> Option<&'a str> {
         match self {
-            Item::Gun(gun_type) => match gun_type {
-                GunType::Shotgun => "images/shotgun.png",
-            },
-            Item::Grenade => "images/grenade.png",
-            Item::_Totem => "images/totem.png",
+            Item::Gun(gun_type, _) => gun_type.def(weapons).map(|def| def.sprite.as_str()),
+            Item::Grenade => Some("images/grenade.png"),
+            Item::Totem => Some("images/totem.png"),
+            Item::Ammo => Some("images/ammo.png"),
         }
     }
 
-    fn bundle(self, tf: Transform, asset_server: &AssetServer) -> PickupBundle {
+    fn texture(&self, asset_server: &AssetServer, weapons: &Assets<WeaponDef>) -> Handle<Image> {
+        self.image_path(weapons)
+            .map_or_else(Handle::default, |path| asset_server.load(path))
+    }
+
+    /// Puts the item on the ground as a pickup
+    pub fn spawn(
+        self,
+        commands: &mut Commands,
+        position: Vec2,
+        asset_server: &AssetServer,
+        weapons: &Assets<WeaponDef>,
+    ) -> Entity {
+        let transform = Transform::from_translation(position.extend(1.1));
+        commands
+            .spawn_bundle(self.bundle(transform, asset_server, weapons))
+            .id()
+    }
+
+    fn bundle(
+        mut self,
+        tf: Transform,
+        asset_server: &AssetServer,
+        weapons: &Assets<WeaponDef>,
+    ) -> PickupBundle {
+        // Loads the gun's definition if it isn't already, so the pickup gets its sprite
+        if let Item::Gun(gun_type, _) = &mut self {
+            gun_type.load(asset_server);
+        }
         PickupBundle {
             sprite_bundle: SpriteBundle {
-                texture: asset_server.load(self.image_path()),
+                texture: self.texture(asset_server, weapons),
                 transform: tf,
                 ..SpriteBundle::default()
             },
@@ -89,49 +135,132 @@ impl DerefMut for IgnoreColliders {
     }
 }
 
-#[derive(Component, Debug, Default)]
-pub struct Inventory(Option<Item>);
-
-impl Deref for Inventory {
-    type Target = Option<Item>;
+/// Which items a slot in the inventory takes
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SlotKind {
+    Primary,
+    Secondary,
+    /// Grenades and totems
+    Throwable,
+}
 
-    fn deref(&self) -> &Self::Target {
-        &self.0
+impl SlotKind {
+    pub fn fits(&self, item: &Item) -> bool {
+        match self {
+            SlotKind::Primary | SlotKind::Secondary => matches!(item, Item::Gun(..)),
+            SlotKind::Throwable => matches!(item, Item::Grenade | Item::Totem),
+        }
     }
 }
 
-impl DerefMut for Inventory {
-    fn deref_mut(&mut self) -> &mut Self::Target {
-        &mut self.0
+#[derive(Clone, Debug)]
+pub struct Slot {
+    pub kind: SlotKind,
+    pub item: Option<Item>,
+}
+
+/// Everything a player carries, one item per slot. Only the selected slot's item is in hand.
+#[derive(Clone, Component, Debug)]
+pub struct Inventory {
+    slots: Vec<Slot>,
+    selected: usize,
+}
+
+impl Default for Inventory {
+    fn default() -> Self {
+        Self::new(&[SlotKind::Primary, SlotKind::Secondary, SlotKind::Throwable])
     }
 }
 
 impl Inventory {
-    #[allow(dead_code)]
+    pub fn new(kinds: &[SlotKind]) -> Self {
+        Self {
+            slots: kinds
+                .iter()
+                .map(|&kind| Slot { kind, item: None })
+                .collect(),
+            selected: 0,
+        }
+    }
+
+    /// Puts the item in the selected slot if it fits there, otherwise in the first free
+    /// slot that takes it. Returns whether there was room.
     pub fn collect_item(&mut self, item: Item) -> bool {
-        if self.is_none() {
-            **self = Some(item);
-            true
+        let free = |slot: &Slot| slot.item.is_none() && slot.kind.fits(&item);
+        let index = if free(&self.slots[self.selected]) {
+            Some(self.selected)
         } else {
-            false
+            self.slots.iter().position(free)
+        };
+        match index {
+            Some(index) => {
+                self.slots[index].item = Some(item);
+                true
+            }
+            None => false,
         }
     }
 
-    #[allow(dead_code)]
+    /// Takes the item out of the selected slot
     pub fn drop_item(&mut self) -> Option<Item> {
-        replace(&mut self.0, None)
+        self.slots[self.selected].item.take()
     }
 
+    /// The item in hand
     pub fn get_item(&self) -> Option<&Item> {
-        self.as_ref()
+        self.slots[self.selected].item.as_ref()
+    }
+
+    pub fn get_item_mut(&mut self) -> Option<&mut Item> {
+        self.slots[self.selected].item.as_mut()
+    }
+
+    pub fn slots(&self) -> &[Slot] {
+        &self.slots
+    }
+
+    pub fn selected(&self) -> usize {
+        self.selected
+    }
+
+    /// Loads the definitions of the guns carried, which stay loaded while they're carried
+    pub fn load_guns(&mut self, asset_server: &AssetServer) {
+        for slot in &mut self.slots {
+            if let Some(Item::Gun(gun_type, _)) = &mut slot.item {
+                gun_type.load(asset_server);
+            }
+        }
+    }
+
+    /// Out of range slots are ignored
+    pub fn select(&mut self, index: usize) {
+        if index < self.slots.len() {
+            self.selected = index;
+        }
+    }
+
+    /// Moves the selection `steps` slots along, wrapping around at either end
+    pub fn cycle(&mut self, steps: i32) {
+        let len = self.slots.len() as i32;
+        self.selected = (self.selected as i32 + steps).rem_euclid(len) as usize;
     }
 }
 
 #[derive(Component)]
 struct InventoryUi;
 
+/// Holds a [`InventoryUiSlot`] for each slot in the controlled player's inventory
+#[derive(Component)]
+struct InventoryUiStrip;
+
+#[derive(Component)]
+struct InventoryUiSlot(usize);
+
+#[derive(Component)]
+struct InventoryUiImage(usize);
+
 #[derive(Component)]
-struct InventoryUiImage;
+struct InventoryUiAmmo;
 
 #[derive(Component)]
 struct UiCamera;
@@ -169,57 +298,201 @@ fn spawn_inventory_ui(mut commands: Commands, asset_server: Res<AssetServer>) {
                 })
                 .with_children(|parent| {
                     parent
-                        .spawn_bundle(ImageBundle {
+                        .spawn_bundle(TextBundle {
+                            style: Style {
+                                margin: Rect {
+                                    right: Val::Px(8.),
+                                    bottom: Val::Px(8.),
+                                    ..Rect::default()
+                                },
+                                ..Style::default()
+                            },
+                            text: Text::with_section(
+                                "",
+                                TextStyle {
+                                    font: asset_server.load("fonts/FiraSans-Bold.ttf"),
+                                    font_size: 24.0,
+                                    color: Color::WHITE,
+                                },
+                                TextAlignment::default(),
+                            ),
+                            ..TextBundle::default()
+                        })
+                        .insert(InventoryUiAmmo);
+                    parent
+                        .spawn_bundle(NodeBundle {
                             style: Style {
-                                size: Size::new(Val::Px(128.), Val::Auto),
+                                margin: Rect::all(Val::Px(4.)),
                                 ..Style::default()
                             },
-                            image: asset_server.load("images/empty.png").into(),
-                            ..ImageBundle::default()
+                            color: Color::NONE.into(),
+                            ..NodeBundle::default()
                         })
-                        .insert(InventoryUiImage);
+                        .insert(InventoryUiStrip);
                 });
         });
 }
 
-fn spawn_pickup(mut commands: Commands, asset_server: Res<AssetServer>) {
-    commands
-        .spawn_bundle(Item::Grenade.bundle(Transform::from_xyz(300., 300., 1.1), &asset_server));
+fn spawn_pickup(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    weapons: Res<Assets<WeaponDef>>,
+    map_init_data: Res<MapInitData>,
+) {
+    for (item, (x, y)) in &map_init_data.pickups {
+        item.clone()
+            .spawn(&mut commands, Vec2::new(*x, *y), &asset_server, &weapons);
+    }
 }
 
 fn update_inventory_ui(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    weapons: Res<Assets<WeaponDef>>,
+    mut weapon_events: EventReader<AssetEvent<WeaponDef>>,
+    curr_players: Query<(&Inventory, ChangeTrackers<Inventory>), With<ControlledPlayer>>,
+    strips: Query<Entity, With<InventoryUiStrip>>,
+    mut ui_slots: Query<(Entity, &InventoryUiSlot, &mut UiColor)>,
+    mut ui_images: Query<(&InventoryUiImage, &mut UiImage)>,
+) {
+    // Gun sprites can change whenever their definition loads or is edited
+    let weapons_changed = weapon_events.iter().count() > 0;
+    let (Ok((inventory, tracker)), Ok(strip)) =
+        (curr_players.get_single(), strips.get_single()) else { return };
+    let texture = |slot: &Slot| {
+        slot.item.as_ref().map_or_else(
+            || asset_server.load("images/empty.png"),
+            |item| item.texture(&asset_server, &weapons),
+        )
+    };
+    let slot_color = |index| {
+        if index == inventory.selected() {
+            UiColor(Color::rgba(1.0, 1.0, 1.0, 0.4))
+        } else {
+            UiColor(Color::rgba(0.0, 0.0, 0.0, 0.4))
+        }
+    };
+
+    // A different player can have a different set of slots
+    if ui_slots.iter().count() != inventory.slots().len() {
+        for (ent, _, _) in ui_slots.iter() {
+            commands.entity(ent).despawn_recursive();
+        }
+        commands.entity(strip).with_children(|parent| {
+            for (index, slot) in inventory.slots().iter().enumerate() {
+                parent
+                    .spawn_bundle(NodeBundle {
+                        style: Style {
+                            size: Size::new(Val::Px(72.), Val::Px(72.)),
+                            margin: Rect::all(Val::Px(4.)),
+                            padding: Rect::all(Val::Px(4.)),
+                            ..Style::default()
+                        },
+                        color: slot_color(index),
+                        ..NodeBundle::default()
+                    })
+                    .insert(InventoryUiSlot(index))
+                    .with_children(|parent| {
+                        parent
+                            .spawn_bundle(ImageBundle {
+                                style: Style {
+                                    size: Size::new(Val::Percent(100.), Val::Percent(100.)),
+                                    ..Style::default()
+                                },
+                                image: texture(slot).into(),
+                                ..ImageBundle::default()
+                            })
+                            .insert(InventoryUiImage(index));
+                    });
+            }
+        });
+        return;
+    }
+
+    if !tracker.is_changed() && !weapons_changed {
+        return;
+    }
+    for (_, ui_slot, mut color) in ui_slots.iter_mut() {
+        *color = slot_color(ui_slot.0);
+    }
+    for (ui_image, mut image) in ui_images.iter_mut() {
+        if let Some(slot) = inventory.slots().get(ui_image.0) {
+            *image = texture(slot).into();
+        }
+    }
+}
+
+/// Rounds left next to the held gun, or that it's reloading
+fn update_ammo_ui(
+    curr_players: Query<(Entity, &Inventory), With<ControlledPlayer>>,
+    guns: Query<(&Parent, &ReloadTimer)>,
+    mut ui_texts: Query<&mut Text, With<InventoryUiAmmo>>,
+) {
+    let Ok(mut text) = ui_texts.get_single_mut() else { return };
+    let Ok((player, inventory)) = curr_players.get_single() else { return };
+    let reloading = guns
+        .iter()
+        .any(|(parent, reload_timer)| parent.0 == player && reload_timer.0.is_some());
+
+    let value = match inventory.get_item() {
+        Some(Item::Gun(..)) if reloading => "Reloading...".to_string(),
+        Some(Item::Gun(_, Some(ammo))) => format!("{} / {}", ammo.magazine, ammo.reserve),
+        _ => String::new(),
+    };
+    // Changing the text at all makes it lay out again
+    if text.sections[0].value != value {
+        text.sections[0].value = value;
+    }
+}
+
+/// Dropped guns show the sprite from their definition, even after it's edited
+fn sync_pickup_sprites(
     asset_server: Res<AssetServer>,
-    curr_players: Query<&Inventory, (With<ControlledPlayer>, Changed<Inventory>)>,
-    mut ui_images: Query<&mut UiImage, With<InventoryUiImage>>,
+    weapons: Res<Assets<WeaponDef>>,
+    mut weapon_events: EventReader<AssetEvent<WeaponDef>>,
+    mut pickups: Query<(&Item, &mut Handle<Image>)>,
 ) {
-    if let (Ok(inventory), Ok(mut image)) = (curr_players.get_single(), ui_images.get_single_mut())
-    {
-        info!("Updating UI for: {inventory:?}");
-
-        *image = asset_server
-            .load(
-                inventory
-                    .get_item()
-                    .map_or("images/empty.png", |item| item.image_path()),
-            )
-            .into();
+    if weapon_events.iter().count() == 0 {
+        return;
+    }
+    for (item, mut texture) in pickups.iter_mut() {
+        if let Item::Gun(..) = item {
+            *texture = item.texture(&asset_server, &weapons);
+        }
     }
 }
 
+/// Grenades get thrown and totems set down, anything else is dropped where the player stands
 fn drop_pickup(
     mut commands: Commands,
     asset_server: Res<AssetServer>,
-    input: Res<PlayerInput>,
-    mut curr_players: Query<
+    weapons: Res<Assets<WeaponDef>>,
+    mut input_ticks: EventReader<PlayerInputTick>,
+    mut players: Query<
         (&mut Inventory, &mut IgnoreColliders, &Transform),
-        With<ControlledPlayer>,
+        With<ControllablePlayer>,
     >,
 ) {
-    if input.throw.was_pressed() {
-        if let Ok((mut inventory, mut ignore_colls, tf)) = curr_players.get_single_mut() {
-            if let Some(item) = inventory.drop_item() {
-                ignore_colls.push(commands.spawn_bundle(item.bundle(*tf, &asset_server)).id());
-            }
+    for PlayerInputTick { input, entity } in input_ticks.iter() {
+        if !input.throw.was_pressed() {
+            continue;
+        }
+        let Ok((mut inventory, mut ignore_colls, tf)) = players.get_mut(*entity) else { continue };
+        match inventory.drop_item() {
+            Some(Item::Grenade) => throw_grenade(
+                &mut commands,
+                &asset_server,
+                *entity,
+                tf.translation,
+                input.aim_direction,
+            ),
+            Some(Item::Totem) => place_totem(&mut commands, &asset_server, tf.translation),
+            Some(item) => ignore_colls.push(
+                commands
+                    .spawn_bundle(item.bundle(*tf, &asset_server, &weapons))
+                    .id(),
+            ),
+            None => {}
         }
     }
 }
@@ -227,6 +500,8 @@ fn drop_pickup(
 fn collide_pickups(
     mut commands: Commands,
     mut events: EventReader<CollisionEvent>,
+    mut progress: ResMut<ObjectiveProgress>,
+    weapons: Res<Assets<WeaponDef>>,
     pickups: Query<&Item>,
     mut players: Query<
         (&mut Inventory, &mut IgnoreColliders),
@@ -253,7 +528,34 @@ fn collide_pickups(
             (players.get_mut(player), pickups.get(pickup))
         {
             if ev.is_started() {
-                if !ignore_colls.contains(&pickup) && inventory.collect_item(item.clone()) {
+                if ignore_colls.contains(&pickup) {
+                    continue;
+                }
+                let collected = match item {
+                    // Every totem picked up counts, even if it's set down again
+                    Item::Totem => {
+                        let collected = inventory.collect_item(item.clone());
+                        if collected {
+                            progress.totems += 1;
+                        }
+                        collected
+                    }
+                    // Ammo goes straight into the held gun, and stays put if there isn't one
+                    Item::Ammo => match inventory.get_item_mut() {
+                        Some(Item::Gun(gun_type, ammo)) => {
+                            if let Some(def) = gun_type.def(&weapons) {
+                                let ammo = ammo.get_or_insert_with(|| def.full_ammo());
+                                ammo.reserve += def.magazine_size * AMMO_PICKUP_MAGAZINES;
+                                true
+                            } else {
+                                false
+                            }
+                        }
+                        _ => false,
+                    },
+                    _ => inventory.collect_item(item.clone()),
+                };
+                if collected {
                     commands.entity(pickup).despawn();
                 }
             } else {
@@ -273,3 +575,49 @@ fn despawn_inventory_ui(
         commands.entity(ui).despawn_recursive();
     });
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn selecting_ignores_slots_that_dont_exist() {
+        let mut inventory = Inventory::default();
+        inventory.select(2);
+        assert_eq!(inventory.selected(), 2);
+        inventory.select(3);
+        assert_eq!(inventory.selected(), 2);
+    }
+
+    #[test]
+    fn cycling_wraps_around_both_ends() {
+        let mut inventory = Inventory::default();
+        inventory.cycle(-1);
+        assert_eq!(inventory.selected(), 2);
+        inventory.cycle(1);
+        assert_eq!(inventory.selected(), 0);
+        inventory.cycle(5);
+        assert_eq!(inventory.selected(), 2);
+        inventory.cycle(-7);
+        assert_eq!(inventory.selected(), 1);
+    }
+
+    #[test]
+    fn items_go_in_hand_when_they_fit_there() {
+        let mut inventory = Inventory::default();
+        inventory.select(1);
+        assert!(inventory.collect_item(Item::Gun(GunType::new("pistol"), None)));
+        assert!(matches!(inventory.get_item(), Some(Item::Gun(..))));
+        // Otherwise the first free slot that takes them
+        assert!(inventory.collect_item(Item::Gun(GunType::new("smg"), None)));
+        assert!(matches!(inventory.slots()[0].item, Some(Item::Gun(..))));
+        assert!(inventory.collect_item(Item::Grenade));
+        assert!(matches!(inventory.slots()[2].item, Some(Item::Grenade)));
+        assert_eq!(inventory.selected(), 1);
+        // No room left
+        assert!(!inventory.collect_item(Item::Totem));
+        assert!(!inventory.collect_item(Item::Gun(GunType::new("rifle"), None)));
+        assert!(matches!(inventory.drop_item(), Some(Item::Gun(..))));
+        assert!(inventory.get_item().is_none());
+    }
+}
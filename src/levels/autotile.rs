@@ -0,0 +1,77 @@
+use std::collections::HashMap;
+
+use bevy::reflect::TypeUuid;
+use serde::Deserialize;
+
+use super::map::{Grid, TileKind};
+use crate::utils::RonAsset;
+
+/// Which texture each tile gets, based on which of its 4 neighbours are walls.
+///
+/// The neighbours are packed into a bitmask: 1 = wall above, 2 = wall right, 4 = wall below,
+/// 8 = wall left. Anything outside the map counts as wall.
+#[derive(Debug, Clone, Deserialize, TypeUuid)]
+#[uuid = "0d6b1e4f-93a2-4c8e-b7f5-6a2d9c3e1f80"]
+pub struct AutotileRules {
+    pub floor: TileRules,
+    pub wall: TileRules,
+}
+
+impl RonAsset for AutotileRules {}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct TileRules {
+    /// Texture for masks without an entry of their own
+    pub default: u16,
+    #[serde(default)]
+    pub masks: HashMap<u8, u16>,
+}
+
+impl TileRules {
+    pub fn texture_index(&self, mask: u8) -> u16 {
+        self.masks.get(&mask).copied().unwrap_or(self.default)
+    }
+}
+
+impl Default for AutotileRules {
+    /// Plain floor and wall, used when the rules file can't be loaded
+    fn default() -> Self {
+        Self {
+            floor: TileRules {
+                default: 4,
+                masks: HashMap::new(),
+            },
+            wall: TileRules {
+                default: 9,
+                masks: HashMap::new(),
+            },
+        }
+    }
+}
+
+impl AutotileRules {
+    /// Picks the texture for every tile
+    pub fn apply(&self, tiles: &Grid<TileKind>) -> Grid<Option<u16>> {
+        let mut textures = Grid::new(tiles.width(), tiles.height(), None);
+        for pos in tiles.positions() {
+            let rules = match tiles[pos] {
+                TileKind::Floor => &self.floor,
+                TileKind::Wall => &self.wall,
+            };
+            textures[pos] = Some(rules.texture_index(wall_mask(tiles, pos)));
+        }
+        textures
+    }
+}
+
+fn wall_mask(tiles: &Grid<TileKind>, (x, y): (u32, u32)) -> u8 {
+    let is_wall = |pos: Option<(u32, u32)>| {
+        pos.and_then(|pos| tiles.get(pos))
+            .map_or(true, |&kind| kind == TileKind::Wall)
+    };
+    let above = is_wall(Some((x, y + 1)));
+    let right = is_wall(Some((x + 1, y)));
+    let below = is_wall(y.checked_sub(1).map(|y| (x, y)));
+    let left = is_wall(x.checked_sub(1).map(|x| (x, y)));
+    above as u8 | (right as u8) << 1 | (below as u8) << 2 | (left as u8) << 3
+}
@@ -0,0 +1,212 @@
+use std::time::Duration;
+
+use bevy::prelude::*;
+use rand::{seq::SliceRandom, Rng};
+
+use crate::{
+    enemy::EnemyKind,
+    health::Health,
+    item::{Inventory, Item},
+    utils::arg_value,
+};
+
+use super::{level_file::LevelFile, map::CaveParams, objective::Objective, LevelLayout};
+
+/// One floor of the campaign
+#[derive(Debug, Clone)]
+pub struct LevelDefinition {
+    pub layout: LevelLayout,
+    /// How many of the layout's enemy spawn points get an enemy, all of them if `None`
+    pub enemy_count: Option<usize>,
+    /// Relative chance of each kind of enemy spawning
+    pub enemy_mix: Vec<(EnemyKind, u32)>,
+    pub objective: Objective,
+    /// Relative chance of each item turning up at a pickup spawn point or being dropped
+    pub loot: Vec<(Item, u32)>,
+    /// Chance for an enemy to drop something from the loot table when it dies
+    pub enemy_drop_chance: f32,
+}
+
+impl LevelDefinition {
+    /// A procedural cave of `size` by `size` tiles with room for `enemy_count` enemies
+    pub fn cave(
+        size: u32,
+        enemy_count: usize,
+        enemy_mix: Vec<(EnemyKind, u32)>,
+        objective: Objective,
+    ) -> Self {
+        Self {
+            layout: LevelLayout::Procedural(CaveParams {
+                width: size,
+                height: size,
+                min_enemy_spawns: enemy_count,
+                ..Default::default()
+            }),
+            enemy_count: Some(enemy_count),
+            enemy_mix,
+            objective,
+            loot: default_loot(),
+            enemy_drop_chance: 0.05,
+        }
+    }
+
+    /// The same floor with its own loot table
+    pub fn with_loot(self, loot: Vec<(Item, u32)>, enemy_drop_chance: f32) -> Self {
+        Self {
+            loot,
+            enemy_drop_chance,
+            ..self
+        }
+    }
+
+    /// Picks which spawn points get an enemy, and what kind
+    pub fn roll_enemies(
+        &self,
+        spawns: &[(u32, u32)],
+        rng: &mut impl Rng,
+    ) -> Vec<(EnemyKind, (u32, u32))> {
+        let count = self.enemy_count.unwrap_or(spawns.len());
+        let positions = spawns
+            .choose_multiple(rng, count)
+            .copied()
+            .collect::<Vec<_>>();
+        positions
+            .into_iter()
+            .map(|pos| {
+                let kind = self
+                    .enemy_mix
+                    .choose_weighted(rng, |(_, weight)| *weight)
+                    .map_or(EnemyKind::Skeleton, |(kind, _)| *kind);
+                (kind, pos)
+            })
+            .collect()
+    }
+
+    /// Picks an item from the loot table, `None` if it's empty
+    pub fn roll_loot(&self, rng: &mut impl Rng) -> Option<Item> {
+        self.loot
+            .choose_weighted(rng, |(_, weight)| *weight)
+            .ok()
+            .map(|(item, _)| item.clone())
+    }
+
+    /// Whether a dying enemy leaves something behind
+    pub fn roll_enemy_drop(&self, rng: &mut impl Rng) -> Option<Item> {
+        if rng.gen::<f32>() < self.enemy_drop_chance {
+            self.roll_loot(rng)
+        } else {
+            None
+        }
+    }
+}
+
+fn default_loot() -> Vec<(Item, u32)> {
+    vec![
+        (Item::Ammo, 4),
+        (Item::Grenade, 2),
+        (Item::gun("pistol"), 2),
+        (Item::gun("smg"), 1),
+        (Item::gun("rifle"), 1),
+        (Item::gun("crossbow"), 1),
+    ]
+}
+
+/// The floors to fight through, one after the other, before the game is won
+pub struct Campaign {
+    pub floors: Vec<LevelDefinition>,
+    pub current_floor: usize,
+    /// What the player carries into the current floor, clones start out with it too
+    pub starting_inventory: Inventory,
+    /// The player's health coming into the current floor, it isn't topped up between floors
+    pub starting_health: Health,
+    /// Keeps the current floor's level file loaded so it can be hot reloaded
+    pub(super) file_handle: Option<Handle<LevelFile>>,
+}
+
+impl Default for Campaign {
+    fn default() -> Self {
+        use EnemyKind::*;
+        use Objective::*;
+        Self::new(vec![
+            // Sidearms to start with, the heavier guns come later
+            LevelDefinition::cave(64, 40, vec![(Skeleton, 1)], All(vec![Kill(20), ReachExit]))
+                .with_loot(
+                    vec![
+                        (Item::Ammo, 3),
+                        (Item::Grenade, 1),
+                        (Item::gun("pistol"), 3),
+                    ],
+                    0.05,
+                ),
+            LevelDefinition::cave(
+                96,
+                100,
+                vec![(Skeleton, 3), (Ghoul, 1)],
+                All(vec![
+                    Any(vec![Kill(40), Survive(Duration::from_secs(90))]),
+                    ReachExit,
+                ]),
+            ),
+            LevelDefinition::cave(
+                128,
+                200,
+                vec![(Skeleton, 4), (Ghoul, 2), (Brute, 1)],
+                All(vec![Kill(50), ReachExit]),
+            )
+            .with_loot(
+                vec![
+                    (Item::Ammo, 5),
+                    (Item::Grenade, 3),
+                    (Item::gun("smg"), 2),
+                    (Item::gun("rifle"), 2),
+                    (Item::gun("crossbow"), 2),
+                ],
+                0.1,
+            ),
+        ])
+    }
+}
+
+impl Campaign {
+    pub fn new(floors: Vec<LevelDefinition>) -> Self {
+        let mut starting_inventory = Inventory::default();
+        starting_inventory.collect_item(Item::gun("shotgun"));
+        Self {
+            floors,
+            current_floor: 0,
+            starting_inventory,
+            starting_health: Health::new(100.0),
+            file_handle: None,
+        }
+    }
+
+    /// Plays just the level file given by `--level <path>` on the command line,
+    /// or the default campaign if there is none.
+    pub fn from_args() -> Self {
+        use Objective::*;
+        match arg_value("--level") {
+            // Every level file has stairs, at the player spawn if they aren't marked
+            Some(path) => Self::new(vec![LevelDefinition {
+                layout: LevelLayout::File(path),
+                enemy_count: None,
+                enemy_mix: vec![(EnemyKind::Skeleton, 1)],
+                objective: All(vec![Kill(50), ReachExit]),
+                loot: default_loot(),
+                enemy_drop_chance: 0.05,
+            }]),
+            None => Self::default(),
+        }
+    }
+
+    pub fn current(&self) -> &LevelDefinition {
+        &self.floors[self.current_floor]
+    }
+
+    pub fn current_mut(&mut self) -> &mut LevelDefinition {
+        &mut self.floors[self.current_floor]
+    }
+
+    pub fn is_last_floor(&self) -> bool {
+        self.current_floor + 1 >= self.floors.len()
+    }
+}
@@ -0,0 +1,123 @@
+use super::map::{Grid, TileKind};
+
+/// A rectangle of tiles, `(x, y)` being its bottom left tile
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TileRect {
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+/// Covers every wall tile with as few rectangles as possible,
+/// greedily growing each one right and then up.
+/// The rectangles never overlap, so together they collide exactly like one box per tile.
+pub fn merge_walls(tiles: &Grid<TileKind>) -> Vec<TileRect> {
+    let mut covered = Grid::new(tiles.width(), tiles.height(), false);
+    let is_free_wall =
+        |covered: &Grid<bool>, pos: (u32, u32)| tiles[pos] == TileKind::Wall && !covered[pos];
+
+    let mut rects = vec![];
+    for (x, y) in tiles.positions() {
+        if !is_free_wall(&covered, (x, y)) {
+            continue;
+        }
+        let mut width = 1;
+        while x + width < tiles.width() && is_free_wall(&covered, (x + width, y)) {
+            width += 1;
+        }
+        let mut height = 1;
+        while y + height < tiles.height()
+            && (x..x + width).all(|rx| is_free_wall(&covered, (rx, y + height)))
+        {
+            height += 1;
+        }
+        for ry in y..y + height {
+            for rx in x..x + width {
+                covered[(rx, ry)] = true;
+            }
+        }
+        rects.push(TileRect {
+            x,
+            y,
+            width,
+            height,
+        });
+    }
+    rects
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::{rngs::StdRng, SeedableRng};
+
+    use super::*;
+    use crate::levels::map::{generate_cave, CaveParams};
+
+    /// Rows from the top down, `#` for walls and `.` for floor
+    fn grid(rows: &[&str]) -> Grid<TileKind> {
+        let height = rows.len() as u32;
+        let mut tiles = Grid::new(rows[0].len() as u32, height, TileKind::Wall);
+        for (row, line) in rows.iter().enumerate() {
+            for (x, c) in line.chars().enumerate() {
+                if c == '.' {
+                    tiles[(x as u32, height - 1 - row as u32)] = TileKind::Floor;
+                }
+            }
+        }
+        tiles
+    }
+
+    /// Checks that every wall tile is in exactly one rectangle and no floor tile is in any
+    fn assert_covers_walls_once(tiles: &Grid<TileKind>, rects: &[TileRect]) {
+        let mut coverage = Grid::new(tiles.width(), tiles.height(), 0);
+        for rect in rects {
+            assert!(rect.width > 0 && rect.height > 0, "{rect:?} is empty");
+            for y in rect.y..rect.y + rect.height {
+                for x in rect.x..rect.x + rect.width {
+                    coverage[(x, y)] += 1;
+                }
+            }
+        }
+        for pos in tiles.positions() {
+            let expected = if tiles[pos] == TileKind::Wall { 1 } else { 0 };
+            assert_eq!(coverage[pos], expected, "{pos:?}");
+        }
+    }
+
+    #[test]
+    fn merges_a_solid_block_into_one_rectangle() {
+        let tiles = grid(&["###", "###"]);
+        assert_eq!(
+            merge_walls(&tiles),
+            vec![TileRect {
+                x: 0,
+                y: 0,
+                width: 3,
+                height: 2
+            }]
+        );
+    }
+
+    #[test]
+    fn covers_every_wall_exactly_once() {
+        let tiles = grid(&["..#..", ".##.#", "#...#", "..#.."]);
+        assert_covers_walls_once(&tiles, &merge_walls(&tiles));
+
+        for seed in 0..3 {
+            let tiles =
+                generate_cave(&CaveParams::default(), &mut StdRng::seed_from_u64(seed)).tiles;
+            let rects = merge_walls(&tiles);
+            let walls = tiles
+                .positions()
+                .filter(|&pos| tiles[pos] == TileKind::Wall)
+                .count();
+            assert!(
+                rects.len() < walls / 2,
+                "seed {seed}: {} rectangles",
+                rects.len()
+            );
+            assert_covers_walls_once(&tiles, &rects);
+        }
+    }
+}
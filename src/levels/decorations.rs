@@ -0,0 +1,82 @@
+use bevy::reflect::TypeUuid;
+use rand::Rng;
+use serde::Deserialize;
+
+use super::map::{Grid, TileKind};
+use crate::utils::RonAsset;
+
+/// Rules for scattering decorations over a level's floor
+/// and covering it with an overlay drawn above the player.
+#[derive(Debug, Clone, Default, Deserialize, TypeUuid)]
+#[uuid = "a4c7e2d9-5b18-4f63-8e0a-91d3b6f2c574"]
+pub struct DecorationRules {
+    /// Tried in order for every floor tile, the first one that rolls its chance is placed
+    #[serde(default)]
+    pub scatter: Vec<Scatter>,
+    /// Texture drawn over floor tiles right below a wall, hiding whatever walks behind it
+    /// so walls look like they have height
+    #[serde(default)]
+    pub overlay: Option<u16>,
+}
+
+impl RonAsset for DecorationRules {}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct Scatter {
+    pub texture: u16,
+    pub chance: f32,
+    pub placement: Placement,
+}
+
+#[derive(Debug, Clone, Copy, Deserialize)]
+pub enum Placement {
+    /// Any floor tile
+    Floor,
+    /// Floor tiles without any wall around them, diagonals included
+    OpenFloor,
+    /// Floor tiles with a wall right above them
+    BelowWall,
+}
+
+impl Placement {
+    fn allows(self, tiles: &Grid<TileKind>, pos: (u32, u32)) -> bool {
+        match self {
+            Placement::Floor => true,
+            Placement::OpenFloor => tiles
+                .neighbours(pos, true)
+                .all(|next| tiles[next] == TileKind::Floor),
+            Placement::BelowWall => is_below_wall(tiles, pos),
+        }
+    }
+}
+
+fn is_below_wall(tiles: &Grid<TileKind>, (x, y): (u32, u32)) -> bool {
+    tiles.get((x, y + 1)) == Some(&TileKind::Wall)
+}
+
+pub struct Decorations {
+    pub scattered: Grid<Option<u16>>,
+    pub overlay: Grid<Option<u16>>,
+}
+
+impl DecorationRules {
+    pub fn decorate(&self, tiles: &Grid<TileKind>, rng: &mut impl Rng) -> Decorations {
+        let mut scattered = Grid::new(tiles.width(), tiles.height(), None);
+        let mut overlay = Grid::new(tiles.width(), tiles.height(), None);
+        for pos in tiles.positions() {
+            if tiles[pos] != TileKind::Floor {
+                continue;
+            }
+            scattered[pos] = self
+                .scatter
+                .iter()
+                .filter(|scatter| scatter.placement.allows(tiles, pos))
+                .find(|scatter| rng.gen::<f32>() < scatter.chance)
+                .map(|scatter| scatter.texture);
+            if is_below_wall(tiles, pos) {
+                overlay[pos] = self.overlay;
+            }
+        }
+        Decorations { scattered, overlay }
+    }
+}
@@ -0,0 +1,178 @@
+use anyhow::{bail, ensure};
+use bevy::{
+    asset::{AssetLoader, BoxedFuture, LoadContext, LoadedAsset},
+    reflect::TypeUuid,
+};
+
+use super::map::{Grid, LevelMap, TileKind};
+
+/// A hand-authored level, loaded either from a Tiled map (see [`super::tmx`])
+/// or from a `.level` file.
+///
+/// The file is an ASCII picture of the map, top row first:
+///
+/// ```text
+/// ; Lines starting with ';' are comments
+/// #########
+/// #P..E.IT#
+/// #########
+/// ```
+///
+/// - `#` or space: wall
+/// - `.`: floor
+/// - `P`: floor where the player spawns, exactly one is required
+/// - `E`: floor where an enemy spawns
+/// - `I`: floor where a pickup spawns
+/// - `T`: floor where a totem spawns
+/// - `X`: floor where the stairs down appear, at the player spawn if there is none
+///
+/// Rows may have different lengths, and the map is surrounded by an implicit ring of wall
+/// so nothing can walk off its edge.
+#[derive(Debug, Clone, TypeUuid)]
+#[uuid = "5b3f8e2a-7c41-4d9b-a0e6-2f1c9d8b7a63"]
+pub struct LevelFile(pub LevelMap);
+
+#[derive(Default)]
+pub struct LevelFileLoader;
+
+impl AssetLoader for LevelFileLoader {
+    fn load<'a>(
+        &'a self,
+        bytes: &'a [u8],
+        load_context: &'a mut LoadContext,
+    ) -> BoxedFuture<'a, Result<(), anyhow::Error>> {
+        Box::pin(async move {
+            let map = parse_level(std::str::from_utf8(bytes)?)?;
+            load_context.set_default_asset(LoadedAsset::new(LevelFile(map)));
+            Ok(())
+        })
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["level"]
+    }
+}
+
+pub fn parse_level(source: &str) -> anyhow::Result<LevelMap> {
+    let rows = source
+        .lines()
+        .map(str::trim_end)
+        .filter(|line| !line.is_empty() && !line.starts_with(';'))
+        .collect::<Vec<_>>();
+    ensure!(!rows.is_empty(), "level has no rows");
+
+    // +2 for the ring of wall around the map
+    let width = rows
+        .iter()
+        .map(|row| row.chars().count())
+        .max()
+        .unwrap_or(0) as u32
+        + 2;
+    let height = rows.len() as u32 + 2;
+    let mut tiles = Grid::new(width, height, TileKind::Wall);
+    let mut player_spawn = None;
+    let mut enemy_spawns = vec![];
+    let mut pickup_spawns = vec![];
+    let mut totem_spawns = vec![];
+    let mut exit = None;
+
+    for (row_idx, row) in rows.iter().enumerate() {
+        let y = height - 2 - row_idx as u32;
+        for (col_idx, c) in row.chars().enumerate() {
+            let pos = (col_idx as u32 + 1, y);
+            tiles[pos] = match c {
+                '#' | ' ' => TileKind::Wall,
+                '.' => TileKind::Floor,
+                'P' => {
+                    ensure!(
+                        player_spawn.is_none(),
+                        "level has more than one player spawn"
+                    );
+                    player_spawn = Some(pos);
+                    TileKind::Floor
+                }
+                'E' => {
+                    enemy_spawns.push(pos);
+                    TileKind::Floor
+                }
+                'I' => {
+                    pickup_spawns.push(pos);
+                    TileKind::Floor
+                }
+                'T' => {
+                    totem_spawns.push(pos);
+                    TileKind::Floor
+                }
+                'X' => {
+                    ensure!(exit.is_none(), "level has more than one exit");
+                    exit = Some(pos);
+                    TileKind::Floor
+                }
+                _ => bail!(
+                    "unknown tile {c:?} in row {}, column {}",
+                    row_idx + 1,
+                    col_idx + 1
+                ),
+            };
+        }
+    }
+
+    let Some(player_spawn) = player_spawn else {
+        bail!("level has no player spawn");
+    };
+    Ok(LevelMap {
+        tiles,
+        player_spawn,
+        enemy_spawns,
+        pickup_spawns,
+        totem_spawns,
+        exit: exit.unwrap_or(player_spawn),
+        texture_layers: vec![],
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reads_the_picture_top_row_first_inside_a_ring_of_wall() {
+        let map = parse_level("; a comment\n####\n#PE.\n#IT\n#.X#\n").unwrap();
+        // Four rows four wide, plus the ring
+        assert_eq!((map.tiles.width(), map.tiles.height()), (6, 6));
+        assert_eq!(map.player_spawn, (2, 3));
+        assert_eq!(map.enemy_spawns, [(3, 3)]);
+        assert_eq!(map.pickup_spawns, [(2, 2)]);
+        assert_eq!(map.totem_spawns, [(3, 2)]);
+        assert_eq!(map.exit, (3, 1));
+        assert_eq!(map.tiles[(4, 3)], TileKind::Floor);
+        // The short row is padded with wall, and so is the ring
+        assert_eq!(map.tiles[(4, 2)], TileKind::Wall);
+        for x in 0..6 {
+            assert_eq!(map.tiles[(x, 0)], TileKind::Wall);
+            assert_eq!(map.tiles[(x, 5)], TileKind::Wall);
+        }
+    }
+
+    #[test]
+    fn the_exit_defaults_to_the_player_spawn() {
+        let map = parse_level("P.E").unwrap();
+        assert_eq!(map.exit, map.player_spawn);
+    }
+
+    #[test]
+    fn rejects_broken_levels() {
+        assert!(parse_level("; only comments\n").is_err());
+        assert!(parse_level("..E").is_err());
+        assert!(parse_level("P.P").is_err());
+        assert!(parse_level("PXX").is_err());
+        assert!(parse_level("P?.").is_err());
+    }
+
+    #[test]
+    fn bundled_level_parses() {
+        let map = parse_level(include_str!("../../assets/levels/crypt.level")).unwrap();
+        assert_eq!(map.tiles[map.player_spawn], TileKind::Floor);
+        assert!(!map.enemy_spawns.is_empty());
+    }
+}
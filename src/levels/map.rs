@@ -1,10 +1,620 @@
-use std::time::Duration;
+use std::{
+    collections::VecDeque,
+    ops::{Index, IndexMut},
+    time::Duration,
+};
+
+use bevy::prelude::*;
+use rand::{rngs::StdRng, seq::SliceRandom, Rng, SeedableRng};
+
+use crate::{enemy::EnemyKind, item::Item, utils::arg_value};
 
 #[derive(Debug, Default)]
 pub struct MapInitData {
     pub player_spawn_position: (f32, f32),
-    pub enemy_spawn_positions: Vec<(f32, f32)>,
+    pub enemy_spawns: Vec<(EnemyKind, (f32, f32))>,
+    /// What each pickup is and where it goes, totems included
+    pub pickups: Vec<(Item, (f32, f32))>,
+    pub exit_position: (f32, f32),
     // Fixme move somewhere more sensible
-    pub kills: usize,
     pub timer: Duration,
 }
+
+/// Seed for every random roll made while building a level,
+/// so the same seed always produces the same map.
+#[derive(Debug, Clone, Copy)]
+pub struct LevelSeed(pub u64);
+
+impl Default for LevelSeed {
+    fn default() -> Self {
+        Self(rand::random())
+    }
+}
+
+impl LevelSeed {
+    /// Reads the seed from `--seed <u64>` on the command line,
+    /// falling back to a random one.
+    pub fn from_args() -> Self {
+        match arg_value("--seed").map(|seed| seed.parse()) {
+            Some(Ok(seed)) => Self(seed),
+            Some(Err(e)) => {
+                warn!("Ignoring invalid --seed: {e}");
+                Self::default()
+            }
+            None => Self::default(),
+        }
+    }
+
+    /// Each floor of the campaign gets its own stream of rolls. The seed is scrambled before
+    /// the floor is mixed in, so neighbouring seeds don't share floors.
+    pub fn rng(&self, floor: usize) -> StdRng {
+        StdRng::seed_from_u64(splitmix64(splitmix64(self.0) ^ floor as u64))
+    }
+}
+
+/// One step of the SplitMix64 generator, which spreads every input bit across the output
+fn splitmix64(x: u64) -> u64 {
+    let mut z = x.wrapping_add(0x9e37_79b9_7f4a_7c15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^ (z >> 31)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TileKind {
+    Floor,
+    Wall,
+}
+
+/// Row-major 2D grid addressed by `(x, y)` tile positions.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Grid<T> {
+    width: u32,
+    height: u32,
+    cells: Vec<T>,
+}
+
+impl<T: Clone> Grid<T> {
+    pub fn new(width: u32, height: u32, fill: T) -> Self {
+        Self {
+            width,
+            height,
+            cells: vec![fill; (width * height) as usize],
+        }
+    }
+}
+
+impl<T> Grid<T> {
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    pub fn in_bounds(&self, (x, y): (u32, u32)) -> bool {
+        x < self.width && y < self.height
+    }
+
+    pub fn get(&self, pos: (u32, u32)) -> Option<&T> {
+        self.in_bounds(pos).then(|| &self[pos])
+    }
+
+    pub fn get_mut(&mut self, pos: (u32, u32)) -> Option<&mut T> {
+        if self.in_bounds(pos) {
+            Some(&mut self[pos])
+        } else {
+            None
+        }
+    }
+
+    pub fn map<U>(&self, f: impl FnMut(&T) -> U) -> Grid<U> {
+        Grid {
+            width: self.width,
+            height: self.height,
+            cells: self.cells.iter().map(f).collect(),
+        }
+    }
+
+    /// All positions in row-major order
+    pub fn positions(&self) -> impl Iterator<Item = (u32, u32)> {
+        let width = self.width;
+        (0..self.height).flat_map(move |y| (0..width).map(move |x| (x, y)))
+    }
+
+    /// The in-bounds neighbours of `pos`, either the 4 orthogonal ones or all 8
+    pub fn neighbours(
+        &self,
+        (x, y): (u32, u32),
+        diagonals: bool,
+    ) -> impl Iterator<Item = (u32, u32)> + '_ {
+        (-1i64..=1)
+            .flat_map(|dy| (-1i64..=1).map(move |dx| (dx, dy)))
+            .filter(move |&(dx, dy)| (dx, dy) != (0, 0) && (diagonals || dx == 0 || dy == 0))
+            .map(move |(dx, dy)| (x as i64 + dx, y as i64 + dy))
+            .filter(|&(nx, ny)| nx >= 0 && ny >= 0)
+            .map(|(nx, ny)| (nx as u32, ny as u32))
+            .filter(|&pos| self.in_bounds(pos))
+    }
+}
+
+impl<T> Index<(u32, u32)> for Grid<T> {
+    type Output = T;
+
+    fn index(&self, (x, y): (u32, u32)) -> &T {
+        assert!(self.in_bounds((x, y)), "({x}, {y}) is outside the grid");
+        &self.cells[(y * self.width + x) as usize]
+    }
+}
+
+impl<T> IndexMut<(u32, u32)> for Grid<T> {
+    fn index_mut(&mut self, (x, y): (u32, u32)) -> &mut T {
+        assert!(self.in_bounds((x, y)), "({x}, {y}) is outside the grid");
+        &mut self.cells[(y * self.width + x) as usize]
+    }
+}
+
+/// Parameters for the cellular automaton cave generator
+#[derive(Debug, Clone)]
+pub struct CaveParams {
+    pub width: u32,
+    pub height: u32,
+    /// Chance for each tile to start out as a wall
+    pub fill_ratio: f32,
+    pub iterations: usize,
+    /// A floor tile with at least this many wall neighbours becomes a wall
+    pub birth_threshold: usize,
+    /// A wall tile with fewer than this many wall neighbours becomes floor
+    pub death_threshold: usize,
+    /// Any tile with at most this many wall neighbours becomes a wall,
+    /// which breaks up large open areas with pillars
+    pub pillar_threshold: Option<usize>,
+    /// Chance for each floor tile to become an enemy spawn
+    pub enemy_spawn_chance: f32,
+    /// Enough enemies have to spawn for the kill goal to be reachable,
+    /// maps that can't fit this many are rerolled and, as a last resort, widened
+    pub min_enemy_spawns: usize,
+    /// Shortest walk, in tiles, from the player spawn to any enemy spawn
+    pub min_enemy_distance: u32,
+    /// How many item pickups to place, fewer if the cave is too cramped
+    pub pickup_count: usize,
+    /// Pickups go in open spots at least this many tiles from each other and the player spawn
+    pub min_pickup_spacing: u32,
+    /// How many totems to place, fewer if the cave is too cramped
+    pub totem_count: usize,
+    /// Same as [`CaveParams::min_pickup_spacing`], but for totems
+    pub min_totem_spacing: u32,
+}
+
+impl Default for CaveParams {
+    fn default() -> Self {
+        Self {
+            width: 128,
+            height: 128,
+            fill_ratio: 0.55,
+            iterations: 40,
+            birth_threshold: 5,
+            death_threshold: 5,
+            pillar_threshold: Some(0),
+            enemy_spawn_chance: 0.1,
+            min_enemy_spawns: 50,
+            min_enemy_distance: 20,
+            pickup_count: 6,
+            min_pickup_spacing: 8,
+            totem_count: 2,
+            min_totem_spacing: 16,
+        }
+    }
+}
+
+/// Layout of a level in tile coordinates, either generated or loaded from a file
+#[derive(Debug, Clone)]
+pub struct LevelMap {
+    pub tiles: Grid<TileKind>,
+    pub player_spawn: (u32, u32),
+    pub enemy_spawns: Vec<(u32, u32)>,
+    pub pickup_spawns: Vec<(u32, u32)>,
+    pub totem_spawns: Vec<(u32, u32)>,
+    /// Where the stairs down appear once the floor's kill goal is met
+    pub exit: (u32, u32),
+    /// Texture index of every tile in each tilemap layer, bottom layer first.
+    /// Empty when the textures should be picked from the tile kinds instead.
+    pub texture_layers: Vec<Grid<Option<u16>>>,
+}
+
+/// How many times to reroll a map that doesn't have room for enough enemies
+const MAX_GENERATION_ATTEMPTS: usize = 100;
+
+pub fn generate_cave(params: &CaveParams, rng: &mut impl Rng) -> LevelMap {
+    let mut attempt = 1;
+    let (tiles, player_spawn) = loop {
+        let tiles = carve_cave(params, rng);
+        // Only a cave without any floor has no open tile, widening gives it some
+        let player_spawn =
+            most_open_tile(&tiles, rng).unwrap_or((params.width / 2, params.height / 2));
+        if enemy_room(&tiles, player_spawn, params) >= params.min_enemy_spawns {
+            break (tiles, player_spawn);
+        }
+        if attempt < MAX_GENERATION_ATTEMPTS {
+            warn!("Cave attempt {attempt} has no room for enough enemies, rerolling");
+            attempt += 1;
+            continue;
+        }
+        warn!(
+            "Widening the cave to fit {} enemies after {attempt} attempts",
+            params.min_enemy_spawns
+        );
+        break (widen_cave(tiles, player_spawn, params), player_spawn);
+    };
+
+    let distances = path_distances(&tiles, player_spawn);
+    let far_enough =
+        |pos: (u32, u32)| distances[pos].map_or(false, |d| d >= params.min_enemy_distance);
+    let mut enemy_spawns = vec![];
+    let mut spare_floor = vec![];
+    for pos in tiles.positions().filter(|&pos| far_enough(pos)) {
+        if rng.gen::<f32>() < params.enemy_spawn_chance {
+            enemy_spawns.push(pos);
+        } else {
+            spare_floor.push(pos);
+        }
+    }
+    if enemy_spawns.len() < params.min_enemy_spawns {
+        spare_floor.shuffle(rng);
+        let missing = params.min_enemy_spawns - enemy_spawns.len();
+        enemy_spawns.extend(spare_floor.into_iter().take(missing));
+    }
+
+    let pickup_spawns = scatter(
+        &tiles,
+        player_spawn,
+        params.pickup_count,
+        params.min_pickup_spacing,
+        rng,
+    );
+    let totem_spawns = scatter(
+        &tiles,
+        player_spawn,
+        params.totem_count,
+        params.min_totem_spacing,
+        rng,
+    );
+
+    // As far a walk from the start as possible
+    let exit = tiles
+        .positions()
+        .min_by_key(|&pos| std::cmp::Reverse(distances[pos]))
+        .unwrap_or(player_spawn);
+
+    LevelMap {
+        tiles,
+        player_spawn,
+        enemy_spawns,
+        pickup_spawns,
+        totem_spawns,
+        exit,
+        texture_layers: vec![],
+    }
+}
+
+/// How many tiles enemies could spawn on, reachable but at least a walk away from the player
+fn enemy_room(tiles: &Grid<TileKind>, player_spawn: (u32, u32), params: &CaveParams) -> usize {
+    let distances = path_distances(tiles, player_spawn);
+    tiles
+        .positions()
+        .filter(|&pos| distances[pos].map_or(false, |d| d >= params.min_enemy_distance))
+        .count()
+}
+
+/// Last resort for a cave rerolling couldn't fit enough enemies in. Carves away the walls
+/// around the floor one layer at a time until they fit, or until only the border is left.
+fn widen_cave(
+    mut tiles: Grid<TileKind>,
+    player_spawn: (u32, u32),
+    params: &CaveParams,
+) -> Grid<TileKind> {
+    let (width, height) = (tiles.width(), tiles.height());
+    let on_border = |(x, y): (u32, u32)| x == 0 || y == 0 || x == width - 1 || y == height - 1;
+    tiles[player_spawn] = TileKind::Floor;
+    while enemy_room(&tiles, player_spawn, params) < params.min_enemy_spawns {
+        // Only walls touching the floor, so the cave stays in one piece
+        let layer = tiles
+            .positions()
+            .filter(|&pos| {
+                tiles[pos] == TileKind::Wall
+                    && !on_border(pos)
+                    && tiles
+                        .neighbours(pos, false)
+                        .any(|next| tiles[next] == TileKind::Floor)
+            })
+            .collect::<Vec<_>>();
+        if layer.is_empty() {
+            warn!("No room left for {} enemies", params.min_enemy_spawns);
+            break;
+        }
+        for pos in layer {
+            tiles[pos] = TileKind::Floor;
+        }
+    }
+    tiles
+}
+
+/// Scatters up to `count` spots over open floor, at least `min_spacing` tiles from each
+/// other and the player spawn so they're spread over the whole cave
+fn scatter(
+    tiles: &Grid<TileKind>,
+    player_spawn: (u32, u32),
+    count: usize,
+    min_spacing: u32,
+    rng: &mut impl Rng,
+) -> Vec<(u32, u32)> {
+    let clearance = wall_clearance(tiles);
+    let mut candidates = tiles
+        .positions()
+        .filter(|&pos| tiles[pos] == TileKind::Floor && clearance[pos] >= 2)
+        .collect::<Vec<_>>();
+    candidates.shuffle(rng);
+
+    let far_apart = |a: (u32, u32), b: (u32, u32)| {
+        let dx = (a.0 as i64 - b.0 as i64).abs();
+        let dy = (a.1 as i64 - b.1 as i64).abs();
+        dx.max(dy) >= min_spacing as i64
+    };
+    let mut spots = vec![];
+    for pos in candidates {
+        if spots.len() >= count {
+            break;
+        }
+        if far_apart(pos, player_spawn) && spots.iter().all(|&spot| far_apart(pos, spot)) {
+            spots.push(pos);
+        }
+    }
+    if spots.len() < count {
+        warn!("Only found room for {} of {count} spots", spots.len());
+    }
+    spots
+}
+
+/// Picks randomly among the floor tiles furthest from any wall
+fn most_open_tile(tiles: &Grid<TileKind>, rng: &mut impl Rng) -> Option<(u32, u32)> {
+    let clearance = wall_clearance(tiles);
+    let max_clearance = tiles.positions().map(|pos| clearance[pos]).max()?;
+    let most_open = tiles
+        .positions()
+        .filter(|&pos| tiles[pos] == TileKind::Floor && clearance[pos] == max_clearance)
+        .collect::<Vec<_>>();
+    most_open.choose(rng).copied()
+}
+
+/// How many steps, diagonals included, each tile is from the nearest wall
+pub fn wall_clearance(tiles: &Grid<TileKind>) -> Grid<u32> {
+    let mut clearance = Grid::new(tiles.width(), tiles.height(), u32::MAX);
+    let mut queue = VecDeque::new();
+    for pos in tiles.positions() {
+        if tiles[pos] == TileKind::Wall {
+            clearance[pos] = 0;
+            queue.push_back(pos);
+        }
+    }
+    while let Some(pos) = queue.pop_front() {
+        let distance = clearance[pos] + 1;
+        for next in tiles.neighbours(pos, true) {
+            if clearance[next] == u32::MAX {
+                clearance[next] = distance;
+                queue.push_back(next);
+            }
+        }
+    }
+    clearance
+}
+
+/// Runs the automaton on a random fill and walls off everything but the largest cave,
+/// so all remaining floor is reachable from anywhere else
+fn carve_cave(params: &CaveParams, rng: &mut impl Rng) -> Grid<TileKind> {
+    let mut tiles = Grid::new(params.width, params.height, TileKind::Floor);
+    for pos in tiles.positions() {
+        let on_border =
+            pos.0 == 0 || pos.1 == 0 || pos.0 == params.width - 1 || pos.1 == params.height - 1;
+        // Always roll so the border doesn't shift the sequence for the rest of the map
+        let is_wall = rng.gen::<f32>() < params.fill_ratio;
+        tiles[pos] = if on_border || is_wall {
+            TileKind::Wall
+        } else {
+            TileKind::Floor
+        };
+    }
+
+    for _ in 0..params.iterations {
+        step_automaton(&mut tiles, params);
+    }
+    remove_hanging_pockets(&mut tiles);
+
+    for pos in floor_regions(&tiles).iter().skip(1).flatten() {
+        tiles[*pos] = TileKind::Wall;
+    }
+    tiles
+}
+
+/// Walks the floor tiles breadth-first from `start`,
+/// giving the path length to every reachable tile
+pub fn path_distances(tiles: &Grid<TileKind>, start: (u32, u32)) -> Grid<Option<u32>> {
+    let mut distances = Grid::new(tiles.width(), tiles.height(), None);
+    if tiles.get(start) != Some(&TileKind::Floor) {
+        return distances;
+    }
+    distances[start] = Some(0);
+    let mut queue = VecDeque::from([start]);
+    while let Some(pos) = queue.pop_front() {
+        let distance = distances[pos].unwrap_or_default() + 1;
+        for next in tiles.neighbours(pos, false) {
+            if tiles[next] == TileKind::Floor && distances[next].is_none() {
+                distances[next] = Some(distance);
+                queue.push_back(next);
+            }
+        }
+    }
+    distances
+}
+
+/// Splits the floor into 4-connected regions, largest first
+pub fn floor_regions(tiles: &Grid<TileKind>) -> Vec<Vec<(u32, u32)>> {
+    let mut visited = Grid::new(tiles.width(), tiles.height(), false);
+    let mut regions = vec![];
+    for start in tiles.positions() {
+        if tiles[start] != TileKind::Floor || visited[start] {
+            continue;
+        }
+        visited[start] = true;
+        let mut region = vec![];
+        let mut queue = VecDeque::from([start]);
+        while let Some(pos) = queue.pop_front() {
+            region.push(pos);
+            for next in tiles.neighbours(pos, false) {
+                if tiles[next] == TileKind::Floor && !visited[next] {
+                    visited[next] = true;
+                    queue.push_back(next);
+                }
+            }
+        }
+        regions.push(region);
+    }
+    // Stable, so equally sized regions stay in scan order
+    regions.sort_by_key(|region| std::cmp::Reverse(region.len()));
+    regions
+}
+
+/// Runs one generation of the automaton over every tile except the border
+fn step_automaton(tiles: &mut Grid<TileKind>, params: &CaveParams) {
+    let mut new_tiles = tiles.clone();
+    for y in 1..(tiles.height() - 1) {
+        for x in 1..(tiles.width() - 1) {
+            let walls = tiles
+                .neighbours((x, y), true)
+                .filter(|&pos| tiles[pos] == TileKind::Wall)
+                .count();
+            let should_be_wall = match tiles[(x, y)] {
+                TileKind::Wall => walls >= params.death_threshold,
+                TileKind::Floor => walls >= params.birth_threshold,
+            } || params.pillar_threshold.map_or(false, |t| walls <= t);
+            new_tiles[(x, y)] = if should_be_wall {
+                TileKind::Wall
+            } else {
+                TileKind::Floor
+            };
+        }
+    }
+    *tiles = new_tiles;
+}
+
+/// Turns single wall tiles surrounded by floor on all 4 sides into floor
+fn remove_hanging_pockets(tiles: &mut Grid<TileKind>) {
+    for y in 1..(tiles.height() - 1) {
+        for x in 1..(tiles.width() - 1) {
+            if tiles[(x, y)] == TileKind::Wall
+                && tiles
+                    .neighbours((x, y), false)
+                    .all(|pos| tiles[pos] == TileKind::Floor)
+            {
+                tiles[(x, y)] = TileKind::Floor;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn generate(seed: u64, floor: usize) -> LevelMap {
+        generate_cave(&CaveParams::default(), &mut LevelSeed(seed).rng(floor))
+    }
+
+    #[test]
+    fn same_seed_and_floor_give_the_same_map() {
+        let map = generate(7, 2);
+        let again = generate(7, 2);
+        assert_eq!(map.tiles, again.tiles);
+        assert_eq!(map.player_spawn, again.player_spawn);
+        assert_eq!(map.enemy_spawns, again.enemy_spawns);
+        assert_eq!(map.pickup_spawns, again.pickup_spawns);
+        assert_eq!(map.totem_spawns, again.totem_spawns);
+        assert_eq!(map.exit, again.exit);
+
+        assert_ne!(map.tiles, generate(7, 3).tiles);
+        assert_ne!(map.tiles, generate(8, 2).tiles);
+        // Neighbouring seeds don't share floors
+        assert_ne!(map.tiles, generate(8, 1).tiles);
+        assert_ne!(map.tiles, generate(6, 3).tiles);
+    }
+
+    #[test]
+    fn spawns_are_on_floor_inside_a_walled_border() {
+        let map = generate(0, 0);
+        let (width, height) = (map.tiles.width(), map.tiles.height());
+        for (x, y) in map.tiles.positions() {
+            if x == 0 || y == 0 || x == width - 1 || y == height - 1 {
+                assert_eq!(map.tiles[(x, y)], TileKind::Wall, "({x}, {y})");
+            }
+        }
+        assert_eq!(map.tiles[map.player_spawn], TileKind::Floor);
+        for &pos in &map.enemy_spawns {
+            assert_eq!(map.tiles[pos], TileKind::Floor, "enemy at {pos:?}");
+        }
+    }
+
+    #[test]
+    fn enemies_spawn_reachable_and_a_walk_away() {
+        let params = CaveParams::default();
+        for seed in 0..3 {
+            let map = generate(seed, 0);
+            let distances = path_distances(&map.tiles, map.player_spawn);
+            for &pos in &map.enemy_spawns {
+                let distance = distances[pos]
+                    .unwrap_or_else(|| panic!("seed {seed}: enemy at {pos:?} can't be reached"));
+                assert!(
+                    distance >= params.min_enemy_distance,
+                    "seed {seed}: enemy at {pos:?} is only {distance} tiles away"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn widens_caves_rerolling_cant_fit_enough_enemies_in() {
+        // Starts out solid wall every time, so only widening can make room
+        let params = CaveParams {
+            width: 32,
+            height: 32,
+            fill_ratio: 1.0,
+            iterations: 1,
+            min_enemy_spawns: 60,
+            min_enemy_distance: 6,
+            ..CaveParams::default()
+        };
+        let map = generate_cave(&params, &mut LevelSeed(3).rng(0));
+        let distances = path_distances(&map.tiles, map.player_spawn);
+        assert!(map.enemy_spawns.len() >= params.min_enemy_spawns);
+        for &pos in &map.enemy_spawns {
+            let distance = distances[pos].expect("enemies spawn where the player can reach");
+            assert!(distance >= params.min_enemy_distance, "{pos:?}");
+        }
+    }
+
+    #[test]
+    fn places_enough_enemies_pickups_and_totems() {
+        let params = CaveParams::default();
+        for seed in 0..3 {
+            let map = generate(seed, 0);
+            assert!(
+                map.enemy_spawns.len() >= params.min_enemy_spawns,
+                "seed {seed}"
+            );
+            assert_eq!(map.pickup_spawns.len(), params.pickup_count, "seed {seed}");
+            assert_eq!(map.totem_spawns.len(), params.totem_count, "seed {seed}");
+            for &pos in map.pickup_spawns.iter().chain(&map.totem_spawns) {
+                assert_eq!(map.tiles[pos], TileKind::Floor, "seed {seed}: {pos:?}");
+            }
+        }
+    }
+}
@@ -1,47 +1,182 @@
+pub mod autotile;
+pub mod campaign;
+pub mod colliders;
+pub mod decorations;
+pub mod level_file;
 pub mod map;
-
-use bevy::{input::mouse::MouseWheel, prelude::*};
+pub mod objective;
+pub mod tmx;
+
+use bevy::{
+    asset::{Asset, LoadState},
+    input::mouse::MouseWheel,
+    prelude::*,
+};
 use bevy_ecs_tilemap::prelude::*;
-use heron::{CollisionLayers, CollisionShape, RigidBody, Velocity};
-
-use crate::{player::PlayerRecording, utils::CommonHandles, GameState};
-
-use self::map::MapInitData;
+use heron::{
+    rapier_plugin::rapier2d::prelude::{ColliderBuilder, Isometry, SharedShape},
+    CollisionEvent, CollisionLayers, CollisionShape, CustomCollisionShape, RigidBody, Velocity,
+};
+
+use crate::{
+    health::Health,
+    inputs::bindings::{Action, ActionMap, Devices},
+    item::{Inventory, Item},
+    player::{ControlledPlayer, PlayerRecording},
+    utils::{CommonHandles, RonLoader},
+    GameLayers, GameState,
+};
+
+use self::{
+    autotile::AutotileRules,
+    campaign::Campaign,
+    decorations::DecorationRules,
+    level_file::{LevelFile, LevelFileLoader},
+    map::{CaveParams, Grid, LevelMap, LevelSeed, MapInitData},
+    objective::{ObjectiveProgress, ObjectiveStatus},
+    tmx::TiledLoader,
+};
 
 pub struct SinglePlayerScene;
 
 impl Plugin for SinglePlayerScene {
     fn build(&self, app: &mut App) {
         app.add_plugin(TilemapPlugin)
+            .add_asset::<LevelFile>()
+            .init_asset_loader::<LevelFileLoader>()
+            .init_asset_loader::<TiledLoader>()
+            .add_asset::<AutotileRules>()
+            .add_asset_loader(RonLoader::<AutotileRules>::new("autotile.ron"))
+            .add_asset::<DecorationRules>()
+            .add_asset_loader(RonLoader::<DecorationRules>::new("decor.ron"))
             .init_resource::<MapInitData>()
+            .init_resource::<ObjectiveProgress>()
+            .insert_resource(LevelSeed::from_args())
+            .insert_resource(Campaign::from_args())
             .add_system(crate::utils::set_texture_filters_to_nearest)
-            .add_system_set(SystemSet::on_enter(GameState::BuildLevel).with_system(build_level))
+            .add_system_set(SystemSet::on_enter(GameState::BuildLevel).with_system(load_level_file))
+            .add_system_set(SystemSet::on_update(GameState::BuildLevel).with_system(build_level))
             .add_system_set(SystemSet::on_enter(GameState::SetupLevel).with_system(level_spawns))
             .add_system_set(
                 SystemSet::on_update(GameState::Playing)
                     .with_system(zoom_update)
-                    .with_system(update_kills_text),
+                    .with_system(update_objective_text)
+                    .with_system(update_health_text)
+                    .with_system(reload_level_file)
+                    .with_system(open_exit)
+                    .with_system(use_exit)
+                    .with_system(relic_contact)
+                    .with_system(check_objective),
             )
             .add_system_set(SystemSet::on_enter(GameState::GameWon).with_system(game_won));
     }
 }
 
+pub const TILE_SIZE: f32 = 32.0;
+const CHUNK_SIZE: u32 = 64;
+
+// Characters are drawn at z 1 and their guns and bullets a bit above that
+const GROUND_Z: f32 = 0.0;
+const DECORATION_Z: f32 = 0.5;
+const OVERLAY_Z: f32 = 10.0;
+
+/// Stairs down in the sprite atlas, outside the part the tilemap uses
+const EXIT_SPRITE: usize = 20;
+
+/// World position of the centre of a tile
+pub fn tile_center((x, y): (u32, u32)) -> (f32, f32) {
+    (
+        x as f32 * TILE_SIZE + TILE_SIZE / 2.0,
+        y as f32 * TILE_SIZE + TILE_SIZE / 2.0,
+    )
+}
+
+/// Where the layout of a level comes from
+#[derive(Debug, Clone)]
+pub enum LevelLayout {
+    Procedural(CaveParams),
+    /// Asset path of a hand-authored `.level` or Tiled `.tmx` file
+    File(String),
+}
+
 #[derive(Component)]
 pub struct MainCamera;
 
 #[derive(Component)]
-pub struct KilledText;
+pub struct ObjectiveText;
+
+#[derive(Component)]
+pub struct HealthText;
+
+#[derive(Component)]
+struct LevelHud;
+
+/// The stairs down to the next floor
+#[derive(Component)]
+pub struct Exit;
+
+/// What the player has to protect on floors with [`objective::Objective::Protect`]
+#[derive(Component)]
+pub struct Relic;
+
+fn load_level_file(mut campaign: ResMut<Campaign>, asset_server: Res<AssetServer>) {
+    campaign.file_handle = match &campaign.current().layout {
+        LevelLayout::File(path) => Some(asset_server.load(path.as_str())),
+        LevelLayout::Procedural(_) => None,
+    };
+}
 
+/// Runs every frame in `GameState::BuildLevel` until the level's layout is available
 fn build_level(
     mut commands: Commands,
     common_handles: Res<CommonHandles>,
     mut game_state: ResMut<State<GameState>>,
     mut map_init_data: ResMut<MapInitData>,
+    mut campaign: ResMut<Campaign>,
+    level_seed: Res<LevelSeed>,
+    level_files: Res<Assets<LevelFile>>,
+    autotile_rules: Res<Assets<AutotileRules>>,
+    decoration_rules: Res<Assets<DecorationRules>>,
     asset_server: Res<AssetServer>,
     atlases: Res<Assets<TextureAtlas>>,
     mut map_query: MapQuery,
 ) {
-    info!("[Scene:SingleplayerLevel:setup]");
+    let Some(autotile_rules) =
+        loaded_or_default(&autotile_rules, &common_handles.autotile_rules, &asset_server)
+    else {
+        return;
+    };
+    let Some(decoration_rules) =
+        loaded_or_default(&decoration_rules, &common_handles.decoration_rules, &asset_server)
+    else {
+        return;
+    };
+
+    let floor = campaign.current_floor;
+    let mut rng = level_seed.rng(floor);
+    let generated: LevelMap = match (&campaign.current().layout, &campaign.file_handle) {
+        (LevelLayout::File(path), Some(handle)) => {
+            if let Some(LevelFile(map)) = level_files.get(handle) {
+                info!("[Scene:SingleplayerLevel:setup] floor {floor}: {path}");
+                map.clone()
+            } else if asset_server.get_load_state(handle) == LoadState::Failed {
+                error!("Couldn't load level {path}, generating one instead");
+                campaign.current_mut().layout = LevelLayout::Procedural(CaveParams::default());
+                campaign.file_handle = None;
+                return;
+            } else {
+                return;
+            }
+        }
+        (LevelLayout::Procedural(params), _) => {
+            info!(
+                "[Scene:SingleplayerLevel:setup] floor {floor}: seed {}",
+                level_seed.0
+            );
+            map::generate_cave(params, &mut rng)
+        }
+        (LevelLayout::File(_), None) => return,
+    };
     commands
         .spawn_bundle(OrthographicCameraBundle::new_2d())
         .insert(MainCamera);
@@ -54,146 +189,95 @@ fn build_level(
     let map_entity = commands.spawn().id();
     let mut map = Map::new(0u16, map_entity);
 
-    let (mut layer_builder, _) = LayerBuilder::new(
-        &mut commands,
-        LayerSettings::new(
-            MapSize(2, 2),
-            ChunkSize(64, 64),
-            TileSize(32.0, 32.0),
-            TextureSize(96.0, 128.0),
-        ),
-        0u16,
-        0u16,
-    );
-
-    let mut outside_tile = TileBundle::default();
-    outside_tile.tile.texture_index = 9;
-    let mut floor_tile = TileBundle::default();
-    floor_tile.tile.texture_index = 4;
+    let texture_layers = if generated.texture_layers.is_empty() {
+        let decorations = decoration_rules.decorate(&generated.tiles, &mut rng);
+        vec![
+            (autotile_rules.apply(&generated.tiles), GROUND_Z),
+            (decorations.scattered, DECORATION_Z),
+            (decorations.overlay, OVERLAY_Z),
+        ]
+    } else {
+        // Authored layers all stay below the characters
+        generated
+            .texture_layers
+            .iter()
+            .enumerate()
+            .map(|(layer_id, textures)| (textures.clone(), layer_id as f32 * 0.1))
+            .collect()
+    };
+    for (layer_id, (textures, z)) in texture_layers.iter().enumerate() {
+        let layer_entity = spawn_tile_layer(
+            &mut commands,
+            &mut map_query,
+            layer_id as u16,
+            textures,
+            texture_handle.clone(),
+        );
+        commands
+            .entity(layer_entity)
+            .insert(Transform::from_xyz(0.0, 0.0, *z));
+        map.add_layer(&mut commands, layer_id as u16, layer_entity);
+    }
 
-    {
-        layer_builder.for_each_tiles_mut(|_ent, data| {
-            *data = Some(if rand::random::<f32>() > 0.55 {
-                floor_tile.clone()
-            } else {
-                outside_tile.clone()
-            });
-        });
-
-        let (size_x, size_y) = {
-            let settings = &layer_builder.settings;
-            (
-                settings.map_size.0 * settings.chunk_size.0,
-                settings.map_size.1 * settings.chunk_size.1,
+    // One body made of solid boxes, so anything pushed into a wall gets pushed back out
+    let wall_rects = colliders::merge_walls(&generated.tiles);
+    info!(
+        "Merged the walls into one collider of {} boxes",
+        wall_rects.len()
+    );
+    let boxes = wall_rects
+        .iter()
+        .map(|rect| {
+            let (width_px, height_px) = (
+                rect.width as f32 * TILE_SIZE,
+                rect.height as f32 * TILE_SIZE,
+            );
+            let center = Isometry::translation(
+                rect.x as f32 * TILE_SIZE + width_px / 2.0,
+                rect.y as f32 * TILE_SIZE + height_px / 2.0,
+            );
+            (center, SharedShape::cuboid(width_px / 2.0, height_px / 2.0))
+        })
+        .collect::<Vec<_>>();
+    // A compound shape needs at least one part
+    if !boxes.is_empty() {
+        let walls = commands
+            .spawn()
+            .insert(GlobalTransform::identity())
+            .insert(RigidBody::Static)
+            .insert(CollisionShape::Custom {
+                shape: CustomCollisionShape::new(ColliderBuilder::compound(boxes)),
+            })
+            .insert(
+                CollisionLayers::none()
+                    .with_group(crate::GameLayers::World)
+                    .with_masks(&[
+                        crate::GameLayers::Player,
+                        crate::GameLayers::Bullets,
+                        crate::GameLayers::Enemies,
+                        crate::GameLayers::Grenades,
+                    ]),
             )
-        };
-
-        for y in 0..size_y {
-            for x in [0, size_x - 1] {
-                *layer_builder.get_tile_mut(TilePos(x, y)).unwrap() = outside_tile.clone();
-            }
-        }
-        for x in 0..size_x {
-            for y in [0, size_y - 1] {
-                *layer_builder.get_tile_mut(TilePos(x, y)).unwrap() = outside_tile.clone();
-            }
-        }
-
-        for _ in 0..40 {
-            let mut new_vals = vec![];
-            for y in 1..(size_y - 1) {
-                for x in 1..(size_x - 1) {
-                    let mut neighbors = 0;
-                    for yp in [y - 1, y, y + 1] {
-                        for xp in [x - 1, x, x + 1] {
-                            if xp == x && yp == y {
-                                continue;
-                            }
-                            if let Ok(tile) = layer_builder
-                                .get_tile(TilePos(xp, yp))
-                                .map(|t| t.tile.texture_index)
-                            {
-                                if tile == 9 {
-                                    neighbors += 1;
-                                }
-                            }
-                        }
-                    }
-                    new_vals.push((x, y, neighbors > 4 || neighbors == 0));
-                }
-            }
-            for (x, y, should_be_wall) in new_vals {
-                layer_builder
-                    .get_tile_mut(TilePos(x, y))
-                    .unwrap()
-                    .tile
-                    .texture_index = if should_be_wall { 9 } else { 4 };
-            }
-        }
-
-        // Get rid of hanging pockets, add the
-        for y in 0..size_y {
-            for x in 0..size_x {
-                let tile_pos = TilePos(x, y);
-                let (x_px, y_px) = (
-                    tile_pos.0 as f32 * 32.0 + 16.0,
-                    tile_pos.1 as f32 * 32.0 + 16.0,
-                );
-                if layer_builder.get_tile(tile_pos).unwrap().tile.texture_index != 9 {
-                    map_init_data.player_spawn_position = (x_px, y_px);
-                    if rand::random::<f32>() < 0.1 {
-                        map_init_data.enemy_spawn_positions.push((x_px, y_px));
-                    }
-                    continue;
-                }
-                let mut neighbors = 0;
-                if x > 0 && x < size_x && y > 0 && y < size_y {
-                    for yp in [y - 1, y, y + 1] {
-                        for xp in [x - 1, x, x + 1] {
-                            if (xp == x && yp == y) || !(xp == x || yp == y) {
-                                continue;
-                            }
-                            if let Ok(tile) = layer_builder
-                                .get_tile(TilePos(xp, yp))
-                                .map(|t| t.tile.texture_index)
-                            {
-                                if tile == 4 {
-                                    neighbors += 1;
-                                }
-                            }
-                        }
-                    }
-                }
-                if neighbors == 4 {
-                    *layer_builder.get_tile_mut(tile_pos).unwrap() = floor_tile.clone();
-                } else {
-                    let child = commands
-                        .spawn()
-                        .insert(GlobalTransform::from_xyz(x_px, y_px, 0.0))
-                        .insert(RigidBody::Static)
-                        .insert(CollisionShape::Cuboid {
-                            half_extends: Vec3::new(16.0, 16.0, 0.0),
-                            border_radius: None,
-                        })
-                        .insert(
-                            CollisionLayers::none()
-                                .with_group(crate::GameLayers::World)
-                                .with_masks(&[
-                                    crate::GameLayers::Player,
-                                    crate::GameLayers::Bullets,
-                                    crate::GameLayers::Enemies,
-                                ]),
-                        )
-                        .id();
-                    commands.entity(map_entity).add_child(child);
-                }
-            }
-        }
+            .id();
+        commands.entity(map_entity).add_child(walls);
     }
-
-    let layer_entity = map_query.build_layer(&mut commands, layer_builder, texture_handle);
-
-    map.add_layer(&mut commands, 0u16, layer_entity);
+    map_init_data.player_spawn_position = tile_center(generated.player_spawn);
+    map_init_data.enemy_spawns = campaign
+        .current()
+        .roll_enemies(&generated.enemy_spawns, &mut rng)
+        .into_iter()
+        .map(|(kind, pos)| (kind, tile_center(pos)))
+        .collect();
+    let loot = generated
+        .pickup_spawns
+        .iter()
+        .filter_map(|&pos| Some((campaign.current().roll_loot(&mut rng)?, tile_center(pos))));
+    let totems = generated
+        .totem_spawns
+        .iter()
+        .map(|&pos| (Item::Totem, tile_center(pos)));
+    map_init_data.pickups = loot.chain(totems).collect();
+    map_init_data.exit_position = tile_center(generated.exit);
 
     commands
         .entity(map_entity)
@@ -235,13 +319,19 @@ fn build_level(
             },
             ..Default::default()
         })
+        .insert(LevelHud)
         .id();
 
     let goal_ent = commands
         .spawn_bundle(TextBundle {
             style: text_style.clone(),
             text: Text::with_section(
-                "Kill 50 enemies as fast as possible!",
+                format!(
+                    "Floor {}/{}: {}!",
+                    floor + 1,
+                    campaign.floors.len(),
+                    campaign.current().objective.description()
+                ),
                 text_textstyle.clone(),
                 text_text_alignment,
             ),
@@ -249,28 +339,272 @@ fn build_level(
         })
         .id();
     let kills_ent = commands
+        .spawn_bundle(TextBundle {
+            style: text_style.clone(),
+            text: Text::with_section(String::new(), text_textstyle.clone(), text_text_alignment),
+            ..Default::default()
+        })
+        .insert(ObjectiveText)
+        .id();
+    let health_ent = commands
+        .spawn_bundle(TextBundle {
+            style: text_style.clone(),
+            text: Text::with_section(String::new(), text_textstyle.clone(), text_text_alignment),
+            ..Default::default()
+        })
+        .insert(HealthText)
+        .id();
+    let seed_ent = commands
         .spawn_bundle(TextBundle {
             style: text_style.clone(),
             text: Text::with_section(
-                format!("Kills: {}", map_init_data.kills),
+                format!("Seed: {}", level_seed.0),
                 text_textstyle.clone(),
                 text_text_alignment,
             ),
             ..Default::default()
         })
-        .insert(KilledText)
         .id();
     commands
         .entity(root_ui_ent)
-        .push_children(&[goal_ent, kills_ent]);
+        .push_children(&[goal_ent, kills_ent, health_ent, seed_ent]);
 
     let _ = game_state.overwrite_set(GameState::SetupLevel);
 }
 
+/// The asset behind `handle`, or its default if it failed to load.
+/// `None` while it's still loading.
+fn loaded_or_default<T: Asset + Clone + Default>(
+    assets: &Assets<T>,
+    handle: &Handle<T>,
+    asset_server: &AssetServer,
+) -> Option<T> {
+    match assets.get(handle) {
+        Some(asset) => Some(asset.clone()),
+        None if asset_server.get_load_state(handle) == LoadState::Failed => {
+            warn!(
+                "Couldn't load {:?}, using defaults",
+                asset_server.get_handle_path(handle)
+            );
+            Some(T::default())
+        }
+        None => None,
+    }
+}
+
+/// Builds one tilemap layer, leaving tiles without a texture empty
+fn spawn_tile_layer(
+    commands: &mut Commands,
+    map_query: &mut MapQuery,
+    layer_id: u16,
+    textures: &Grid<Option<u16>>,
+    texture_handle: Handle<Image>,
+) -> Entity {
+    let (mut layer_builder, _) = LayerBuilder::new(
+        commands,
+        LayerSettings::new(
+            MapSize(
+                (textures.width() + CHUNK_SIZE - 1) / CHUNK_SIZE,
+                (textures.height() + CHUNK_SIZE - 1) / CHUNK_SIZE,
+            ),
+            ChunkSize(CHUNK_SIZE, CHUNK_SIZE),
+            TileSize(TILE_SIZE, TILE_SIZE),
+            TextureSize(96.0, 128.0),
+        ),
+        0u16,
+        layer_id,
+    );
+
+    for pos in textures.positions() {
+        if let Some(texture_index) = textures[pos] {
+            let mut tile = TileBundle::default();
+            tile.tile.texture_index = texture_index;
+            let _ = layer_builder.set_tile(TilePos(pos.0, pos.1), tile);
+        }
+    }
+
+    map_query.build_layer(commands, layer_builder, texture_handle)
+}
+
+/// Rebuilds the level whenever its file changes on disk
+fn reload_level_file(
+    mut commands: Commands,
+    mut game_state: ResMut<State<GameState>>,
+    mut events: EventReader<AssetEvent<LevelFile>>,
+    campaign: Res<Campaign>,
+    level_ents: Query<Entity, LevelEntityFilter>,
+    mut map_query: MapQuery,
+) {
+    let Some(file_handle) = &campaign.file_handle else { return };
+    let modified = events.iter().any(|ev| match ev {
+        AssetEvent::Modified { handle } => handle == file_handle,
+        _ => false,
+    });
+    if !modified {
+        return;
+    }
+
+    info!("Level file changed, rebuilding");
+    despawn_level(&mut commands, &mut map_query, &level_ents);
+    let _ = game_state.overwrite_set(GameState::BuildLevel);
+}
+
+/// Everything spawned for a level besides the map itself and the characters,
+/// which `level_spawns` clears out
+type LevelEntityFilter = Or<(With<MainCamera>, With<LevelHud>, With<Item>, With<Exit>)>;
+
+fn despawn_level(
+    commands: &mut Commands,
+    map_query: &mut MapQuery,
+    level_ents: &Query<Entity, LevelEntityFilter>,
+) {
+    map_query.despawn(commands, 0u16);
+    for ent in level_ents.iter() {
+        commands.entity(ent).despawn_recursive();
+    }
+}
+
+/// Opens the stairs down once the rest of the objective is done
+fn open_exit(
+    mut commands: Commands,
+    common_handles: Res<CommonHandles>,
+    map_init_data: Res<MapInitData>,
+    campaign: Res<Campaign>,
+    progress: Res<ObjectiveProgress>,
+    exits: Query<(), With<Exit>>,
+) {
+    if !campaign.current().objective.exit_open(&progress) || !exits.is_empty() {
+        return;
+    }
+
+    info!("Opening the stairs");
+    let (x, y) = map_init_data.exit_position;
+    commands
+        .spawn_bundle(SpriteSheetBundle {
+            sprite: TextureAtlasSprite::new(EXIT_SPRITE),
+            texture_atlas: common_handles.player_sprites.clone(),
+            transform: Transform::from_xyz(x, y, 0.9),
+            ..Default::default()
+        })
+        .insert(Exit)
+        .insert(RigidBody::Sensor)
+        .insert(CollisionShape::Cuboid {
+            half_extends: Vec3::new(TILE_SIZE / 2.0, TILE_SIZE / 2.0, 0.0),
+            border_radius: None,
+        })
+        .insert(
+            CollisionLayers::none()
+                .with_group(GameLayers::Exit)
+                .with_mask(GameLayers::Player),
+        );
+}
+
+fn use_exit(
+    mut events: EventReader<CollisionEvent>,
+    mut progress: ResMut<ObjectiveProgress>,
+    exits: Query<(), With<Exit>>,
+    players: Query<(), With<ControlledPlayer>>,
+) {
+    for ev in events.iter().filter(|ev| ev.is_started()) {
+        let (e1, e2) = ev.rigid_body_entities();
+        if (exits.get(e1).is_ok() && players.get(e2).is_ok())
+            || (exits.get(e2).is_ok() && players.get(e1).is_ok())
+        {
+            progress.reached_exit = true;
+        }
+    }
+}
+
+/// Any enemy reaching the relic destroys it
+fn relic_contact(
+    mut commands: Commands,
+    mut events: EventReader<CollisionEvent>,
+    mut progress: ResMut<ObjectiveProgress>,
+    relics: Query<(), With<Relic>>,
+) {
+    for ev in events.iter().filter(|ev| ev.is_started()) {
+        let (e1, e2) = ev.rigid_body_entities();
+        let (l1, l2) = ev.collision_layers();
+        let relic = if l1.contains_group(GameLayers::Relic)
+            && l2.contains_group(GameLayers::Enemies)
+        {
+            e1
+        } else if l2.contains_group(GameLayers::Relic) && l1.contains_group(GameLayers::Enemies) {
+            e2
+        } else {
+            continue;
+        };
+        if relics.get(relic).is_ok() {
+            info!("The relic was destroyed");
+            progress.relic_destroyed = true;
+            commands.entity(relic).despawn_recursive();
+        }
+    }
+}
+
+/// Takes the player down to the next floor once the objective is done, or wins the game
+/// after the last one. Failing the objective starts the floor over.
+fn check_objective(
+    mut commands: Commands,
+    time: Res<Time>,
+    mut game_state: ResMut<State<GameState>>,
+    mut campaign: ResMut<Campaign>,
+    mut progress: ResMut<ObjectiveProgress>,
+    mut recordings: ResMut<PlayerRecording>,
+    players: Query<(&Inventory, &Health), With<ControlledPlayer>>,
+    level_ents: Query<Entity, LevelEntityFilter>,
+    mut map_query: MapQuery,
+) {
+    progress.elapsed += time.delta();
+    match campaign.current().objective.status(&progress) {
+        ObjectiveStatus::Pending => {}
+        ObjectiveStatus::Failed => {
+            info!("Objective failed, starting the floor over");
+            // Forget what this attempt recorded, like cloning does but without adding a clone
+            let current_loop = recordings.current_loop;
+            recordings.inputs.truncate(current_loop);
+            recordings.current_tick = 0;
+            let _ = game_state.overwrite_set(GameState::SetupLevel);
+        }
+        ObjectiveStatus::Done if campaign.is_last_floor() => {
+            let _ = game_state.overwrite_set(GameState::GameWon);
+        }
+        ObjectiveStatus::Done => {
+            info!("Objective done, taking the stairs down");
+            if let Ok((inventory, health)) = players.get_single() {
+                campaign.starting_inventory = inventory.clone();
+                campaign.starting_health = health.clone();
+            }
+            campaign.current_floor += 1;
+            // Clones only know how to replay the floor they were recorded on
+            *recordings = PlayerRecording::default();
+            despawn_level(&mut commands, &mut map_query, &level_ents);
+            let _ = game_state.overwrite_set(GameState::BuildLevel);
+        }
+    }
+}
+
+/// Scrolling on its own switches inventory slots, so zooming needs [`Action::Zoom`] held
 fn zoom_update(
+    action_map: Res<ActionMap>,
+    keys: Res<Input<KeyCode>>,
+    mouse: Res<Input<MouseButton>>,
+    gamepads: Res<Gamepads>,
+    gamepad_buttons: Res<Input<GamepadButton>>,
     mut scroll: EventReader<MouseWheel>,
     mut query: Query<&mut OrthographicProjection, With<MainCamera>>,
 ) {
+    let devices = Devices {
+        keys: &keys,
+        mouse: &mouse,
+        gamepad_buttons: &gamepad_buttons,
+        gamepad: gamepads.iter().next().copied(),
+    };
+    if !action_map.pressed(Action::Zoom, &devices) {
+        // Skip what was scrolled in the meantime
+        let _ = scroll.iter().count();
+        return;
+    }
     for mut projection in query.iter_mut() {
         for ev in scroll.iter() {
             projection.scale = (projection.scale - ev.y / 20.0).max(0.01);
@@ -278,28 +612,48 @@ fn zoom_update(
     }
 }
 
-pub fn update_kills_text(
-    map_init_data: Res<MapInitData>,
-    mut query: Query<&mut Text, With<KilledText>>,
+pub fn update_objective_text(
+    campaign: Res<Campaign>,
+    progress: Res<ObjectiveProgress>,
+    mut query: Query<&mut Text, With<ObjectiveText>>,
 ) {
     let mut text = query.single_mut();
-    text.sections[0].value = format!("Kills: {}", map_init_data.kills);
+    text.sections[0].value = campaign.current().objective.progress_text(&progress);
+}
+
+pub fn update_health_text(
+    players: Query<&Health, With<ControlledPlayer>>,
+    mut query: Query<&mut Text, With<HealthText>>,
+) {
+    let (Ok(health), Ok(mut text)) = (players.get_single(), query.get_single_mut()) else {
+        return;
+    };
+    text.sections[0].value = format!("Health: {}/{}", health.current.max(0.0), health.max);
 }
 
 pub fn level_spawns(
     mut commands: Commands,
     common_handles: Res<CommonHandles>,
     mut game_state: ResMut<State<GameState>>,
-    mut map_init_data: ResMut<MapInitData>,
+    map_init_data: Res<MapInitData>,
+    mut progress: ResMut<ObjectiveProgress>,
     recordings: Res<PlayerRecording>,
+    campaign: Res<Campaign>,
     asset_server: Res<AssetServer>,
-    char_query: Query<Entity, (With<Velocity>, With<RigidBody>)>,
+    char_query: Query<
+        Entity,
+        Or<(
+            (With<Velocity>, With<RigidBody>),
+            With<Exit>,
+            With<Relic>,
+            With<Item>,
+        )>,
+    >,
 ) {
     info!("Setting up level ents");
-    // Reset kills
-    map_init_data.kills = 0;
+    *progress = ObjectiveProgress::default();
 
-    // Clear existing enemies
+    // Clear existing enemies, the relic and pickups, and close the stairs again
     for ent in char_query.iter() {
         commands.entity(ent).despawn_recursive();
     }
@@ -310,6 +664,8 @@ pub fn level_spawns(
         &common_handles,
         map_init_data.player_spawn_position,
         &asset_server,
+        campaign.starting_inventory.clone(),
+        campaign.starting_health.clone(),
         false,
         10000, // doesn't matter
     );
@@ -321,14 +677,35 @@ pub fn level_spawns(
             &common_handles,
             map_init_data.player_spawn_position,
             &asset_server,
+            campaign.starting_inventory.clone(),
+            campaign.starting_health.clone(),
             true,
             id,
         );
     }
 
+    if campaign.current().objective.needs_relic() {
+        let (x, y) = map_init_data.player_spawn_position;
+        commands
+            .spawn_bundle(SpriteSheetBundle {
+                sprite: TextureAtlasSprite::new(48),
+                texture_atlas: common_handles.player_sprites.clone(),
+                transform: Transform::from_xyz(x, y, 0.9),
+                ..Default::default()
+            })
+            .insert(Relic)
+            .insert(RigidBody::Sensor)
+            .insert(CollisionShape::Sphere { radius: 12.0 })
+            .insert(
+                CollisionLayers::none()
+                    .with_group(GameLayers::Relic)
+                    .with_mask(GameLayers::Enemies),
+            );
+    }
+
     // Spawn enemies
-    for (x_px, y_px) in map_init_data.enemy_spawn_positions.iter().cloned() {
-        crate::enemy::spawn_enemy(&mut commands, &common_handles, Vec2::new(x_px, y_px));
+    for &(kind, (x_px, y_px)) in &map_init_data.enemy_spawns {
+        crate::enemy::spawn_enemy(&mut commands, &common_handles, Vec2::new(x_px, y_px), kind);
     }
 
     let _ = game_state.overwrite_set(GameState::Playing);
@@ -0,0 +1,212 @@
+use std::time::Duration;
+
+/// What has to be done to clear a floor
+#[derive(Debug, Clone)]
+pub enum Objective {
+    /// Kill this many enemies
+    Kill(usize),
+    /// Stay alive for this long
+    Survive(Duration),
+    /// Walk onto the stairs, which only appear once the rest of the objective is done
+    ReachExit,
+    /// Pick up this many totems
+    CollectTotems(usize),
+    /// Keep every enemy away from the relic. Never done on its own, so combine it with
+    /// something like [`Objective::Survive`].
+    Protect,
+    /// Every one of these
+    All(Vec<Objective>),
+    /// Any one of these
+    Any(Vec<Objective>),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ObjectiveStatus {
+    Pending,
+    Done,
+    Failed,
+}
+
+/// How far the player has got on the current floor, reset whenever it starts over
+#[derive(Debug, Default, Clone)]
+pub struct ObjectiveProgress {
+    pub kills: usize,
+    pub elapsed: Duration,
+    pub totems: usize,
+    pub reached_exit: bool,
+    pub relic_destroyed: bool,
+}
+
+impl Objective {
+    pub fn status(&self, progress: &ObjectiveProgress) -> ObjectiveStatus {
+        use ObjectiveStatus::*;
+        let done_if = |done| if done { Done } else { Pending };
+        match self {
+            Objective::Kill(count) => done_if(progress.kills >= *count),
+            Objective::Survive(duration) => done_if(progress.elapsed >= *duration),
+            Objective::ReachExit => done_if(progress.reached_exit),
+            Objective::CollectTotems(count) => done_if(progress.totems >= *count),
+            Objective::Protect if progress.relic_destroyed => Failed,
+            Objective::Protect => Pending,
+            Objective::All(objectives) => {
+                let statuses = objectives
+                    .iter()
+                    .map(|objective| objective.status(progress));
+                statuses.fold(Done, |acc, status| match (acc, status) {
+                    (Failed, _) | (_, Failed) => Failed,
+                    (Pending, _) | (_, Pending) => Pending,
+                    (Done, Done) => Done,
+                })
+            }
+            Objective::Any(objectives) => {
+                let statuses = objectives
+                    .iter()
+                    .map(|objective| objective.status(progress));
+                statuses.fold(Failed, |acc, status| match (acc, status) {
+                    (Done, _) | (_, Done) => Done,
+                    (Pending, _) | (_, Pending) => Pending,
+                    (Failed, Failed) => Failed,
+                })
+            }
+        }
+    }
+
+    /// Whether everything but reaching the exit is done, so the stairs should be open
+    pub fn exit_open(&self, progress: &ObjectiveProgress) -> bool {
+        let at_exit = ObjectiveProgress {
+            reached_exit: true,
+            ..progress.clone()
+        };
+        self.needs(&|objective| matches!(objective, Objective::ReachExit))
+            && self.status(&at_exit) == ObjectiveStatus::Done
+    }
+
+    pub fn needs_relic(&self) -> bool {
+        self.needs(&|objective| matches!(objective, Objective::Protect))
+    }
+
+    fn needs(&self, predicate: &dyn Fn(&Objective) -> bool) -> bool {
+        match self {
+            Objective::All(objectives) | Objective::Any(objectives) => objectives
+                .iter()
+                .any(|objective| objective.needs(predicate)),
+            objective => predicate(objective),
+        }
+    }
+
+    /// The goal as shown in the HUD, e.g. "kill 20 enemies and take the stairs"
+    pub fn description(&self) -> String {
+        match self {
+            Objective::Kill(count) => format!("kill {count} enemies"),
+            Objective::Survive(duration) => format!("survive for {} seconds", duration.as_secs()),
+            Objective::ReachExit => "take the stairs".to_string(),
+            Objective::CollectTotems(count) => format!("collect {count} totems"),
+            Objective::Protect => "keep the enemies away from the relic".to_string(),
+            Objective::All(objectives) => join(objectives, " and "),
+            Objective::Any(objectives) => join(objectives, " or "),
+        }
+    }
+
+    /// Counters for each part of the goal, e.g. "Kills: 3/20"
+    pub fn progress_text(&self, progress: &ObjectiveProgress) -> String {
+        match self {
+            Objective::Kill(count) => format!("Kills: {}/{count}", progress.kills),
+            Objective::Survive(duration) => format!(
+                "Time: {}/{}s",
+                progress.elapsed.as_secs().min(duration.as_secs()),
+                duration.as_secs()
+            ),
+            // The stairs showing up says enough
+            Objective::ReachExit => String::new(),
+            Objective::CollectTotems(count) => format!("Totems: {}/{count}", progress.totems),
+            Objective::Protect if progress.relic_destroyed => "Relic: lost".to_string(),
+            Objective::Protect => "Relic: safe".to_string(),
+            Objective::All(objectives) | Objective::Any(objectives) => objectives
+                .iter()
+                .map(|objective| objective.progress_text(progress))
+                .filter(|text| !text.is_empty())
+                .collect::<Vec<_>>()
+                .join("  "),
+        }
+    }
+}
+
+fn join(objectives: &[Objective], separator: &str) -> String {
+    objectives
+        .iter()
+        .map(|objective| match objective {
+            // Keep nested combinations readable
+            Objective::All(_) | Objective::Any(_) => format!("({})", objective.description()),
+            objective => objective.description(),
+        })
+        .collect::<Vec<_>>()
+        .join(separator)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ObjectiveStatus::*;
+
+    fn progress(kills: usize, relic_destroyed: bool) -> ObjectiveProgress {
+        ObjectiveProgress {
+            kills,
+            relic_destroyed,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn all_needs_every_part_and_fails_with_any() {
+        let objective = Objective::All(vec![
+            Objective::Kill(5),
+            Objective::Survive(Duration::from_secs(10)),
+        ]);
+        assert_eq!(objective.status(&progress(5, false)), Pending);
+        let survived = ObjectiveProgress {
+            elapsed: Duration::from_secs(10),
+            ..progress(5, false)
+        };
+        assert_eq!(objective.status(&survived), Done);
+
+        let protect = Objective::All(vec![Objective::Kill(5), Objective::Protect]);
+        assert_eq!(protect.status(&progress(5, false)), Pending);
+        assert_eq!(protect.status(&progress(0, true)), Failed);
+        assert_eq!(Objective::All(vec![]).status(&progress(0, false)), Done);
+    }
+
+    #[test]
+    fn any_needs_one_part_and_fails_with_all() {
+        let objective = Objective::Any(vec![Objective::Kill(5), Objective::Protect]);
+        assert_eq!(objective.status(&progress(0, false)), Pending);
+        assert_eq!(objective.status(&progress(5, true)), Done);
+        assert_eq!(objective.status(&progress(0, true)), Pending);
+
+        let lost = Objective::Any(vec![Objective::Protect, Objective::Protect]);
+        assert_eq!(lost.status(&progress(0, true)), Failed);
+        assert_eq!(Objective::Any(vec![]).status(&progress(0, false)), Failed);
+    }
+
+    #[test]
+    fn nested_objectives_fold_all_the_way_down() {
+        let objective = Objective::All(vec![
+            Objective::Any(vec![Objective::Kill(5), Objective::CollectTotems(2)]),
+            Objective::Protect,
+        ]);
+        let totems = ObjectiveProgress {
+            totems: 2,
+            ..progress(0, false)
+        };
+        // Protecting the relic is never done, only failed
+        assert_eq!(objective.status(&totems), Pending);
+        assert_eq!(objective.status(&progress(5, true)), Failed);
+    }
+
+    #[test]
+    fn the_stairs_open_once_the_rest_is_done() {
+        let objective = Objective::All(vec![Objective::Kill(5), Objective::ReachExit]);
+        assert!(!objective.exit_open(&progress(4, false)));
+        assert!(objective.exit_open(&progress(5, false)));
+        assert!(!Objective::Kill(5).exit_open(&progress(5, false)));
+    }
+}
@@ -0,0 +1,207 @@
+use std::io::BufReader;
+
+use anyhow::{bail, ensure, Context};
+use bevy::asset::{AssetLoader, BoxedFuture, LoadContext, LoadedAsset};
+use tiled::{LayerData, PropertyValue};
+
+use super::{
+    level_file::LevelFile,
+    map::{Grid, LevelMap, TileKind},
+    TILE_SIZE,
+};
+
+/// Loads maps made in [Tiled](https://www.mapeditor.org/) from `.tmx` files.
+/// Tilesets have to be embedded in the map rather than kept in separate `.tsx` files.
+///
+/// - Every tile layer becomes a tilemap layer, bottom layer first.
+///   Texture indices are taken straight from the tileset, so it has to be laid out like the
+///   level tileset.
+/// - A tile is a wall if the tileset gives it a `wall` bool property set to `true`,
+///   in any layer. Tiles left empty in every layer are walls as well.
+/// - Objects whose type is `player`, `enemy`, `pickup` or `totem` mark the spawn points.
+///   Exactly one `player` is required.
+/// - An object of type `exit` marks where the stairs down appear,
+///   they appear at the player spawn if there is none.
+#[derive(Default)]
+pub struct TiledLoader;
+
+impl AssetLoader for TiledLoader {
+    fn load<'a>(
+        &'a self,
+        bytes: &'a [u8],
+        load_context: &'a mut LoadContext,
+    ) -> BoxedFuture<'a, Result<(), anyhow::Error>> {
+        Box::pin(async move {
+            let tiled_map = tiled::parse(BufReader::new(bytes))?;
+            let map = convert_map(&tiled_map)
+                .with_context(|| format!("invalid Tiled map {:?}", load_context.path()))?;
+            load_context.set_default_asset(LoadedAsset::new(LevelFile(map)));
+            Ok(())
+        })
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["tmx"]
+    }
+}
+
+fn convert_map(tiled_map: &tiled::Map) -> anyhow::Result<LevelMap> {
+    ensure!(
+        tiled_map.tile_width as f32 == TILE_SIZE && tiled_map.tile_height as f32 == TILE_SIZE,
+        "tiles have to be {TILE_SIZE}px, not {}x{}px",
+        tiled_map.tile_width,
+        tiled_map.tile_height,
+    );
+    let (width, height) = (tiled_map.width, tiled_map.height);
+
+    let mut texture_layers = vec![];
+    let mut is_wall = Grid::new(width, height, false);
+    let mut is_empty = Grid::new(width, height, true);
+    for layer in &tiled_map.layers {
+        let LayerData::Finite(rows) = &layer.tiles else {
+            bail!("layer {:?} is infinite, which isn't supported", layer.name);
+        };
+        let mut textures = Grid::new(width, height, None);
+        // Tiled stores rows top down, while tile positions go bottom up
+        for (row_idx, row) in rows.iter().enumerate() {
+            let y = height - 1 - row_idx as u32;
+            for (x, layer_tile) in row.iter().enumerate() {
+                let pos = (x as u32, y);
+                let Some(tileset) = tiled_map.get_tileset_by_gid(layer_tile.gid) else {
+                    continue;
+                };
+                let id = layer_tile.gid - tileset.first_gid;
+                textures[pos] = Some(id as u16);
+                is_empty[pos] = false;
+                let wall_property = tileset
+                    .tiles
+                    .iter()
+                    .find(|tile| tile.id == id)
+                    .and_then(|tile| tile.properties.get("wall"));
+                if let Some(PropertyValue::BoolValue(true)) = wall_property {
+                    is_wall[pos] = true;
+                }
+            }
+        }
+        texture_layers.push(textures);
+    }
+
+    let mut tiles = Grid::new(width, height, TileKind::Floor);
+    for pos in tiles.positions() {
+        if is_wall[pos] || is_empty[pos] {
+            tiles[pos] = TileKind::Wall;
+        }
+    }
+
+    let mut player_spawn = None;
+    let mut enemy_spawns = vec![];
+    let mut pickup_spawns = vec![];
+    let mut totem_spawns = vec![];
+    let mut exit = None;
+    for object in tiled_map
+        .object_groups
+        .iter()
+        .flat_map(|group| &group.objects)
+    {
+        // Tile objects are anchored at their bottom left, everything else at the top left
+        let center_y = if object.gid != 0 {
+            object.y - object.height / 2.0
+        } else {
+            object.y + object.height / 2.0
+        };
+        let tile_x = ((object.x + object.width / 2.0) / TILE_SIZE).floor();
+        let tile_y = height as f32 - 1.0 - (center_y / TILE_SIZE).floor();
+        if tile_x < 0.0 || tile_y < 0.0 || tile_x >= width as f32 || tile_y >= height as f32 {
+            bail!("object {:?} is outside the map", object.name);
+        }
+        let pos = (tile_x as u32, tile_y as u32);
+
+        match object.obj_type.as_str() {
+            "player" => {
+                ensure!(player_spawn.is_none(), "map has more than one player spawn");
+                player_spawn = Some(pos);
+            }
+            "enemy" => enemy_spawns.push(pos),
+            "pickup" => pickup_spawns.push(pos),
+            "totem" => totem_spawns.push(pos),
+            "exit" => {
+                ensure!(exit.is_none(), "map has more than one exit");
+                exit = Some(pos);
+            }
+            _ => continue,
+        }
+        ensure!(
+            tiles[pos] == TileKind::Floor,
+            "object {:?} is inside a wall",
+            object.name
+        );
+    }
+
+    let Some(player_spawn) = player_spawn else {
+        bail!("map has no object of type \"player\"");
+    };
+    Ok(LevelMap {
+        tiles,
+        player_spawn,
+        enemy_spawns,
+        pickup_spawns,
+        totem_spawns,
+        exit: exit.unwrap_or(player_spawn),
+        texture_layers,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::levels::level_file::parse_level;
+
+    const CRYPT: &str = include_str!("../../assets/levels/crypt.tmx");
+
+    fn convert(source: &str) -> anyhow::Result<LevelMap> {
+        convert_map(&tiled::parse(BufReader::new(source.as_bytes()))?)
+    }
+
+    #[test]
+    fn matches_the_same_level_drawn_as_text() {
+        let map = convert(CRYPT).unwrap();
+        let text = parse_level(include_str!("../../assets/levels/crypt.level")).unwrap();
+        // The text version has a ring of wall around it that the Tiled one draws itself
+        let shift = |(x, y): (u32, u32)| (x + 1, y + 1);
+        for pos in map.tiles.positions() {
+            assert_eq!(map.tiles[pos], text.tiles[shift(pos)], "at {pos:?}");
+        }
+        assert_eq!(shift(map.player_spawn), text.player_spawn);
+        assert_eq!(shift(map.exit), text.exit);
+        let shifted = |spawns: &[(u32, u32)]| {
+            let mut spawns = spawns.iter().copied().map(shift).collect::<Vec<_>>();
+            spawns.sort_unstable();
+            spawns
+        };
+        let sorted = |spawns: &[(u32, u32)]| {
+            let mut spawns = spawns.to_vec();
+            spawns.sort_unstable();
+            spawns
+        };
+        assert_eq!(shifted(&map.enemy_spawns), sorted(&text.enemy_spawns));
+        assert_eq!(shifted(&map.pickup_spawns), sorted(&text.pickup_spawns));
+        assert_eq!(map.texture_layers.len(), 1);
+    }
+
+    #[test]
+    fn rejects_spawns_inside_walls() {
+        let source = CRYPT.replace(
+            r#"type="player" x="144" y="432""#,
+            r#"type="player" x="16" y="16""#,
+        );
+        assert_ne!(source, CRYPT);
+        assert!(convert(&source).is_err());
+    }
+
+    #[test]
+    fn rejects_maps_without_a_player() {
+        let source = CRYPT.replace(r#"type="player""#, r#"type="decoration""#);
+        assert_ne!(source, CRYPT);
+        assert!(convert(&source).is_err());
+    }
+}
@@ -2,6 +2,7 @@
 #![feature(let_else)]
 #![allow(clippy::too_many_arguments)]
 
+use bevy::asset::AssetServerSettings;
 use bevy::diagnostic::{FrameTimeDiagnosticsPlugin, LogDiagnosticsPlugin};
 use bevy::prelude::*;
 use bevy::window::WindowMode;
@@ -12,13 +13,16 @@ use heron::prelude::*;
 use resources::audio_channels::AudioChannels;
 
 mod enemy;
+mod grenade;
 pub mod gun;
+mod health;
 mod inputs;
 mod item;
 mod levels;
 mod menus;
 mod player;
 pub mod resources;
+mod totem;
 mod utils;
 
 fn main() {
@@ -32,6 +36,11 @@ fn main() {
             title: "Bevy Cursed Tomb".to_string(),
             ..Default::default()
         })
+        // Hot reload assets such as level files while the game runs
+        .insert_resource(AssetServerSettings {
+            watch_for_changes: cfg!(not(target_arch = "wasm32")),
+            ..Default::default()
+        })
         .insert_resource(ClearColor(Color::rgb(0.11, 0.039, 0.004)))
         .init_resource::<AudioChannels>()
         // Standard Bevy functionality
@@ -46,10 +55,15 @@ fn main() {
         //.add_plugin(WorldInspectorPlugin::new())
         .add_plugin(player::PlayerPlugin)
         .add_plugin(menus::MainMenuScene)
+        .add_plugin(menus::GameOverScene)
+        .add_plugin(menus::SettingsScene)
         .add_plugin(levels::SinglePlayerScene)
         .add_plugin(item::ItemPlugin)
         .add_plugin(gun::GunPlugin)
+        .add_plugin(grenade::GrenadePlugin)
+        .add_plugin(totem::TotemPlugin)
         .add_plugin(enemy::EnemyPlugin)
+        .add_plugin(health::HealthPlugin)
         .add_state(GameState::MainMenu)
         .run();
 }
@@ -57,10 +71,12 @@ fn main() {
 #[derive(Clone, Eq, PartialEq, Debug, Hash)]
 pub enum GameState {
     MainMenu,
+    Settings,
     BuildLevel,
     SetupLevel,
     Playing,
     GameWon,
+    GameOver,
 }
 
 #[derive(Clone, Eq, PartialEq, Debug, Hash, PhysicsLayer)]
@@ -70,4 +86,7 @@ pub enum GameLayers {
     Player,
     Enemies,
     Pickups,
+    Exit,
+    Relic,
+    Grenades,
 }
@@ -0,0 +1,129 @@
+use bevy::prelude::*;
+use bevy_kira_audio::Audio;
+
+use crate::{
+    levels::{campaign::Campaign, map::MapInitData},
+    menus::common,
+    player::PlayerRecording,
+    resources::audio_channels::AudioChannels,
+    GameState,
+};
+
+use super::common::{Disabled, HOVERED_COLOR, NORMAL_COLOR, PRESSED_COLOR};
+
+#[derive(Debug, Clone, Copy, Component)]
+pub enum GameOverButtonId {
+    Retry,
+    MainMenu,
+}
+
+pub fn handle_buttons(
+    mut game_state: ResMut<State<GameState>>,
+    mut campaign: ResMut<Campaign>,
+    mut recordings: ResMut<PlayerRecording>,
+    mut map_init_data: ResMut<MapInitData>,
+    audio: Res<Audio>,
+    channels: Res<AudioChannels>,
+    mut interaction_query: Query<
+        (&Interaction, &mut UiColor, &GameOverButtonId),
+        (Changed<Interaction>, With<Button>, Without<Disabled>),
+    >,
+) -> anyhow::Result<()> {
+    for (interaction, mut color, button_id) in interaction_query.iter_mut() {
+        match *interaction {
+            Interaction::Clicked => {
+                *color = PRESSED_COLOR;
+                // Either way the clones start over
+                *recordings = PlayerRecording::default();
+                match button_id {
+                    GameOverButtonId::Retry => {
+                        game_state.overwrite_set(GameState::BuildLevel)?;
+                    }
+                    GameOverButtonId::MainMenu => {
+                        *campaign = Campaign::from_args();
+                        *map_init_data = MapInitData::default();
+                        // The main menu starts the music over
+                        audio.stop_channel(&channels.music);
+                        game_state.overwrite_set(GameState::MainMenu)?;
+                    }
+                }
+            }
+            Interaction::Hovered => {
+                *color = HOVERED_COLOR;
+            }
+            Interaction::None => {
+                *color = NORMAL_COLOR;
+            }
+        }
+    }
+    Ok(())
+}
+
+pub fn setup(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    campaign: Res<Campaign>,
+    query: Query<Entity>,
+) {
+    info!("[Scene:GameOver:setup]");
+    for ent in query.iter() {
+        commands.entity(ent).despawn_recursive();
+    }
+    commands.spawn_bundle(UiCameraBundle::default());
+
+    commands
+        .spawn_bundle(NodeBundle {
+            style: Style {
+                size: Size::new(Val::Percent(100.0), Val::Percent(100.0)),
+                justify_content: JustifyContent::Center,
+                flex_direction: FlexDirection::ColumnReverse,
+                ..Default::default()
+            },
+            color: Color::NONE.into(),
+            ..Default::default()
+        })
+        .with_children(|parent| {
+            parent.spawn_bundle(TextBundle {
+                style: common::text_style(),
+                text: Text::with_section(
+                    format!("You died on floor {}", campaign.current_floor + 1),
+                    common::text_textstyle(&*asset_server),
+                    common::button_text_alignment(),
+                ),
+                ..Default::default()
+            });
+            spawn_button(parent, &asset_server, "Retry", GameOverButtonId::Retry);
+            spawn_button(
+                parent,
+                &asset_server,
+                "Main Menu",
+                GameOverButtonId::MainMenu,
+            );
+        });
+}
+
+fn spawn_button(
+    parent: &mut ChildBuilder,
+    asset_server: &AssetServer,
+    label: &str,
+    button_id: GameOverButtonId,
+) {
+    parent
+        .spawn_bundle(ButtonBundle {
+            style: common::button_style(),
+            color: NORMAL_COLOR,
+            ..Default::default()
+        })
+        .with_children(|parent| {
+            parent.spawn_bundle(TextBundle {
+                style: common::text_style(),
+                text: Text::with_section(
+                    label,
+                    common::text_textstyle(asset_server),
+                    common::button_text_alignment(),
+                ),
+                ..Default::default()
+            });
+        })
+        .insert(button_id);
+}
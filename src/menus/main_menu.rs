@@ -39,6 +39,9 @@ pub fn handle_buttons(
                     ButtonId::SinglePlayer => {
                         game_state.overwrite_set(GameState::BuildLevel)?;
                     }
+                    ButtonId::Settings => {
+                        game_state.overwrite_set(GameState::Settings)?;
+                    }
                     ButtonId::Quit => {
                         app_exit_events.send(AppExit);
                     }
@@ -115,8 +118,7 @@ pub fn setup(
                         ..Default::default()
                     });
                 })
-                .insert(ButtonId::Settings)
-                .insert(Disabled);
+                .insert(ButtonId::Settings);
             parent
                 .spawn_bundle(ButtonBundle {
                     style: common::button_style(),
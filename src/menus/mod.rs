@@ -3,7 +3,9 @@ use bevy::prelude::*;
 use crate::{utils::log_error, GameState};
 
 pub mod common;
+pub mod game_over;
 pub mod main_menu;
+pub mod settings;
 
 pub struct MainMenuScene;
 
@@ -17,3 +19,36 @@ impl Plugin for MainMenuScene {
             .add_system_set(SystemSet::on_exit(GameState::MainMenu).with_system(common::cleanup));
     }
 }
+
+pub struct GameOverScene;
+
+impl Plugin for GameOverScene {
+    fn build(&self, app: &mut App) {
+        app.add_system_set(SystemSet::on_enter(GameState::GameOver).with_system(game_over::setup))
+            .add_system_set(
+                SystemSet::on_update(GameState::GameOver)
+                    .with_system(game_over::handle_buttons.chain(log_error)),
+            )
+            .add_system_set(SystemSet::on_exit(GameState::GameOver).with_system(common::cleanup));
+    }
+}
+
+pub struct SettingsScene;
+
+impl Plugin for SettingsScene {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<settings::Rebinding>()
+            .add_system_set(SystemSet::on_enter(GameState::Settings).with_system(settings::setup))
+            .add_system_set(
+                SystemSet::on_update(GameState::Settings)
+                    .with_system(
+                        settings::handle_buttons
+                            .chain(log_error)
+                            .label("settings_buttons"),
+                    )
+                    .with_system(settings::capture_binding.after("settings_buttons"))
+                    .with_system(settings::update_binding_texts.after("settings_buttons")),
+            )
+            .add_system_set(SystemSet::on_exit(GameState::Settings).with_system(common::cleanup));
+    }
+}
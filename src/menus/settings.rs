@@ -0,0 +1,273 @@
+use bevy::prelude::*;
+use bevy_kira_audio::Audio;
+
+use crate::{
+    inputs::bindings::{Action, ActionMap, Binding, BINDING_SLOTS},
+    menus::common,
+    resources::audio_channels::AudioChannels,
+    GameState,
+};
+
+use super::common::{Disabled, HOVERED_COLOR, NORMAL_COLOR, PRESSED_COLOR};
+
+#[derive(Debug, Clone, Copy, Component)]
+pub enum SettingsButtonId {
+    /// Rebinds one of the action's slots
+    Rebind(Action, usize),
+    Reset,
+    Back,
+}
+
+/// Shows what one of an action's slots is bound to
+#[derive(Component)]
+pub struct BindingText(Action, usize);
+
+/// The action and slot waiting for a new binding, if any
+#[derive(Default)]
+pub struct Rebinding(Option<(Action, usize)>);
+
+pub fn handle_buttons(
+    mut game_state: ResMut<State<GameState>>,
+    mut action_map: ResMut<ActionMap>,
+    mut rebinding: ResMut<Rebinding>,
+    audio: Res<Audio>,
+    channels: Res<AudioChannels>,
+    mut interaction_query: Query<
+        (&Interaction, &mut UiColor, &SettingsButtonId),
+        (Changed<Interaction>, With<Button>, Without<Disabled>),
+    >,
+) -> anyhow::Result<()> {
+    for (interaction, mut color, button_id) in interaction_query.iter_mut() {
+        match *interaction {
+            // The click goes to the binding being captured instead
+            Interaction::Clicked if rebinding.0.is_some() => {}
+            Interaction::Clicked => {
+                *color = PRESSED_COLOR;
+                match *button_id {
+                    SettingsButtonId::Rebind(action, slot) => {
+                        rebinding.0 = Some((action, slot));
+                    }
+                    SettingsButtonId::Reset => {
+                        *action_map = ActionMap::default();
+                    }
+                    SettingsButtonId::Back => {
+                        if let Err(e) = action_map.save() {
+                            error!("Couldn't save the controls: {e}");
+                        }
+                        // The main menu starts the music over
+                        audio.stop_channel(&channels.music);
+                        game_state.overwrite_set(GameState::MainMenu)?;
+                    }
+                }
+            }
+            Interaction::Hovered => {
+                *color = HOVERED_COLOR;
+            }
+            Interaction::None => {
+                *color = NORMAL_COLOR;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Binds the next key, mouse button or gamepad button pressed to the slot being rebound.
+/// Escape cancels.
+pub fn capture_binding(
+    keys: Res<Input<KeyCode>>,
+    mouse: Res<Input<MouseButton>>,
+    gamepad_buttons: Res<Input<GamepadButton>>,
+    mut action_map: ResMut<ActionMap>,
+    mut rebinding: ResMut<Rebinding>,
+) {
+    // Skip the frame the rebind button was clicked in, or the click itself gets bound
+    if rebinding.is_changed() {
+        return;
+    }
+    let Some((action, slot)) = rebinding.0 else { return };
+    if keys.just_pressed(KeyCode::Escape) {
+        rebinding.0 = None;
+        return;
+    }
+    let binding = keys
+        .get_just_pressed()
+        .next()
+        .map(|&key| Binding::Key(key))
+        .or_else(|| {
+            mouse
+                .get_just_pressed()
+                .next()
+                .map(|&button| Binding::Mouse(button))
+        })
+        .or_else(|| {
+            gamepad_buttons
+                .get_just_pressed()
+                .next()
+                .map(|button| Binding::Gamepad(button.1))
+        });
+    if let Some(binding) = binding {
+        match action_map.rebind(action, slot, binding) {
+            Some(other) => info!("Bound {action:?} to {binding}, swapping it with {other:?}"),
+            None => info!("Bound {action:?} to {binding}"),
+        }
+        rebinding.0 = None;
+    }
+}
+
+pub fn update_binding_texts(
+    asset_server: Res<AssetServer>,
+    action_map: Res<ActionMap>,
+    rebinding: Res<Rebinding>,
+    mut texts: Query<(&mut Text, &BindingText)>,
+) {
+    if !action_map.is_changed() && !rebinding.is_changed() {
+        return;
+    }
+    for (mut text, &BindingText(action, slot)) in texts.iter_mut() {
+        *text = if rebinding.0 == Some((action, slot)) {
+            binding_section(&asset_server, "Press a button...".to_string())
+        } else {
+            binding_section(&asset_server, binding_label(&action_map, action, slot))
+        };
+    }
+}
+
+fn binding_label(action_map: &ActionMap, action: Action, slot: usize) -> String {
+    action_map
+        .bindings(action)
+        .get(slot)
+        .map_or_else(|| "-".to_string(), ToString::to_string)
+}
+
+fn binding_section(asset_server: &AssetServer, label: String) -> Text {
+    Text::with_section(
+        label,
+        TextStyle {
+            font_size: 20.0,
+            ..common::text_textstyle(asset_server)
+        },
+        common::button_text_alignment(),
+    )
+}
+
+pub fn setup(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    action_map: Res<ActionMap>,
+    mut rebinding: ResMut<Rebinding>,
+) {
+    info!("[Scene:Settings:setup]");
+    commands.spawn_bundle(UiCameraBundle::default());
+    *rebinding = Rebinding::default();
+
+    commands
+        .spawn_bundle(NodeBundle {
+            style: Style {
+                size: Size::new(Val::Percent(100.0), Val::Percent(100.0)),
+                justify_content: JustifyContent::Center,
+                flex_direction: FlexDirection::ColumnReverse,
+                ..Default::default()
+            },
+            color: Color::NONE.into(),
+            ..Default::default()
+        })
+        .with_children(|parent| {
+            parent.spawn_bundle(TextBundle {
+                style: common::text_style(),
+                text: Text::with_section(
+                    "Click a binding, then press its new button",
+                    common::text_textstyle(&*asset_server),
+                    common::button_text_alignment(),
+                ),
+                ..Default::default()
+            });
+            for action in Action::ALL {
+                parent
+                    .spawn_bundle(NodeBundle {
+                        style: Style {
+                            align_self: AlignSelf::Center,
+                            align_items: AlignItems::Center,
+                            ..Default::default()
+                        },
+                        color: Color::NONE.into(),
+                        ..Default::default()
+                    })
+                    .with_children(|parent| {
+                        spawn_binding_row(parent, &asset_server, &action_map, action)
+                    });
+            }
+            spawn_button(parent, &asset_server, "Reset", SettingsButtonId::Reset);
+            spawn_button(parent, &asset_server, "Back", SettingsButtonId::Back);
+        });
+}
+
+/// The action's name followed by a button for each of its slots
+fn spawn_binding_row(
+    parent: &mut ChildBuilder,
+    asset_server: &AssetServer,
+    action_map: &ActionMap,
+    action: Action,
+) {
+    parent.spawn_bundle(TextBundle {
+        style: Style {
+            size: Size::new(Val::Px(160.0), Val::Auto),
+            ..common::text_style()
+        },
+        text: binding_section(asset_server, action.label().to_string()),
+        ..Default::default()
+    });
+    for slot in 0..BINDING_SLOTS {
+        parent
+            .spawn_bundle(ButtonBundle {
+                style: Style {
+                    size: Size::new(Val::Px(220.0), Val::Px(24.0)),
+                    margin: Rect::all(Val::Px(1.0)),
+                    ..common::button_style()
+                },
+                color: NORMAL_COLOR,
+                ..Default::default()
+            })
+            .with_children(|parent| {
+                parent
+                    .spawn_bundle(TextBundle {
+                        style: common::text_style(),
+                        text: binding_section(
+                            asset_server,
+                            binding_label(action_map, action, slot),
+                        ),
+                        ..Default::default()
+                    })
+                    .insert(BindingText(action, slot));
+            })
+            .insert(SettingsButtonId::Rebind(action, slot));
+    }
+}
+
+fn spawn_button(
+    parent: &mut ChildBuilder,
+    asset_server: &AssetServer,
+    label: &str,
+    button_id: SettingsButtonId,
+) {
+    parent
+        .spawn_bundle(ButtonBundle {
+            style: Style {
+                margin: Rect::all(Val::Px(4.0)),
+                ..common::button_style()
+            },
+            color: NORMAL_COLOR,
+            ..Default::default()
+        })
+        .with_children(|parent| {
+            parent.spawn_bundle(TextBundle {
+                style: common::text_style(),
+                text: Text::with_section(
+                    label,
+                    common::text_textstyle(asset_server),
+                    common::button_text_alignment(),
+                ),
+                ..Default::default()
+            });
+        })
+        .insert(button_id);
+}
@@ -1,5 +1,7 @@
 use bevy::prelude::*;
 
+mod player_dodge;
+mod player_health;
 mod player_movement;
 
 use heron::{CollisionLayers, CollisionShape, RigidBody, RotationConstraints, Velocity};
@@ -7,6 +9,7 @@ use player_movement::player_movement;
 
 use crate::{
     gun::GunType,
+    health::{Health, HitInvulnerability},
     inputs::PlayerInput,
     item::{IgnoreColliders, Inventory, Item},
     levels::MainCamera,
@@ -15,11 +18,17 @@ use crate::{
     GameState,
 };
 
+use self::player_dodge::{player_dodge, tick_dodges};
+use self::player_health::{
+    enemy_contact_damage, player_death, track_enemy_contacts, EnemyContacts, INVULNERABILITY_SECS,
+};
 use self::player_movement::{
-    player_clone, player_shooting, player_shooting_input, record_player, replay_recordings,
-    ControllablePlayer, PlayerInputTick,
+    player_clone, player_shooting, player_shooting_input, player_switch_slot, record_player,
+    replay_recordings,
 };
 
+pub use self::player_movement::{ControllablePlayer, PlayerInputTick};
+
 #[derive(Default)]
 pub struct PlayerRecording {
     pub current_loop: usize,
@@ -41,6 +50,12 @@ impl Plugin for PlayerPlugin {
                     .with_system(player_movement)
                     .with_system(replay_recordings)
                     .with_system(player_shooting_input)
+                    .with_system(track_enemy_contacts)
+                    .with_system(enemy_contact_damage)
+                    .with_system(player_death)
+                    .with_system(player_switch_slot)
+                    .with_system(player_dodge)
+                    .with_system(tick_dodges)
                     .with_system(player_shooting), // This apparently removes the GameState condition
                                                    //.with_run_criteria(
                                                    //    FixedTimestep::steps_per_second(60.0),
@@ -55,6 +70,8 @@ pub fn spawn_player(
     common_handles: &CommonHandles,
     pos: (f32, f32),
     asset_server: &AssetServer,
+    mut inventory: Inventory,
+    health: Health,
     is_clone: bool,
     clone_id: usize,
 ) {
@@ -63,13 +80,15 @@ pub fn spawn_player(
     } else {
         info!("Spawning player!");
     }
+    inventory.load_guns(asset_server);
+    let gun_type = match inventory.get_item() {
+        Some(Item::Gun(gun_type, _)) => gun_type.clone(),
+        _ => GunType::default(),
+    };
     let starting_gun = commands
-        .spawn_bundle(GunType::Shotgun.create_bundle(&*asset_server))
+        .spawn_bundle(gun_type.create_bundle(&*asset_server))
         .id();
 
-    let mut starting_inventory = Inventory::default();
-    starting_inventory.collect_item(Item::Gun(GunType::Shotgun));
-
     let player_ent = commands
         .spawn_bundle(ControllablePlayerBundle::default())
         .insert_bundle(SpriteSheetBundle {
@@ -78,7 +97,9 @@ pub fn spawn_player(
             transform: Transform::from_xyz(pos.0, pos.1, 1.0),
             ..Default::default()
         })
-        .insert(starting_inventory)
+        .insert(inventory)
+        .insert(health)
+        .insert(HitInvulnerability(INVULNERABILITY_SECS))
         .insert(IgnoreColliders::default())
         .insert(RigidBody::Dynamic)
         .insert(RotationConstraints::lock())
@@ -91,6 +112,7 @@ pub fn spawn_player(
                     crate::GameLayers::World,
                     crate::GameLayers::Enemies,
                     crate::GameLayers::Pickups,
+                    crate::GameLayers::Exit,
                 ]),
         )
         .add_child(starting_gun)
@@ -107,6 +129,7 @@ pub struct ControllablePlayerBundle {
     controllable: ControllablePlayer,
     stats: PlayerStats,
     inventory: Inventory,
+    enemy_contacts: EnemyContacts,
 }
 
 #[derive(Component)]
@@ -0,0 +1,98 @@
+use bevy::prelude::*;
+use heron::{CollisionLayers, Velocity};
+
+use crate::{health::Invulnerable, GameLayers};
+
+use super::{
+    player_health::EnemyContacts,
+    player_movement::{ControllablePlayer, PlayerInputTick},
+};
+
+const DODGE_SPEED: f32 = 600.0;
+const DODGE_SECS: f32 = 0.2;
+/// Counted from the start of the roll
+const DODGE_COOLDOWN_SECS: f32 = 0.8;
+
+/// Rolling in a fixed direction, straight through enemies
+#[derive(Component)]
+pub struct Dodging {
+    timer: Timer,
+    velocity: Vec3,
+}
+
+#[derive(Component)]
+pub struct DodgeCooldown(Timer);
+
+pub fn player_dodge(
+    mut commands: Commands,
+    mut input_ticks: EventReader<PlayerInputTick>,
+    mut players: Query<
+        (&mut CollisionLayers, Option<&Invulnerable>),
+        (
+            With<ControllablePlayer>,
+            Without<Dodging>,
+            Without<DodgeCooldown>,
+        ),
+    >,
+) {
+    for PlayerInputTick { input, entity } in input_ticks.iter() {
+        if !input.dodge.was_pressed() {
+            continue;
+        }
+        let Ok((mut layers, invulnerable)) = players.get_mut(*entity) else { continue };
+        // Roll where the player is heading, or where they're aiming when standing still
+        let direction = if input.move_direction != Vec2::ZERO {
+            input.move_direction
+        } else {
+            input.aim_direction
+        };
+        *layers = layers.without_mask(GameLayers::Enemies);
+        commands
+            .entity(*entity)
+            .insert(Dodging {
+                timer: Timer::from_seconds(DODGE_SECS, false),
+                velocity: direction.normalize_or_zero().extend(0.0) * DODGE_SPEED,
+            })
+            .insert(DodgeCooldown(Timer::from_seconds(
+                DODGE_COOLDOWN_SECS,
+                false,
+            )));
+        // Rolling right after a hit mustn't cut the hit's invulnerability short
+        if invulnerable.map_or(true, |inv| inv.remaining_secs() < DODGE_SECS) {
+            commands
+                .entity(*entity)
+                .insert(Invulnerable::new(DODGE_SECS));
+        }
+    }
+}
+
+pub fn tick_dodges(
+    mut commands: Commands,
+    time: Res<Time>,
+    mut dodging: Query<(
+        Entity,
+        &mut Dodging,
+        &mut Velocity,
+        &mut CollisionLayers,
+        &mut EnemyContacts,
+    )>,
+    mut cooldowns: Query<(Entity, &mut DodgeCooldown)>,
+) {
+    for (ent, mut dodge, mut velocity, mut layers, mut contacts) in dodging.iter_mut() {
+        if dodge.timer.tick(time.delta()).finished() {
+            *layers = layers.with_mask(GameLayers::Enemies);
+            // Enemies left behind during the roll may never have reported that they stopped
+            // touching, and those still touching start touching again now the mask is back
+            contacts.clear();
+            commands.entity(ent).remove::<Dodging>();
+        } else {
+            // Movement leaves dodging players alone, but may have run before the roll started
+            velocity.linear = dodge.velocity;
+        }
+    }
+    for (ent, mut cooldown) in cooldowns.iter_mut() {
+        if cooldown.0.tick(time.delta()).finished() {
+            commands.entity(ent).remove::<DodgeCooldown>();
+        }
+    }
+}
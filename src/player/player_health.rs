@@ -0,0 +1,105 @@
+use std::cmp::Ordering;
+
+use bevy::prelude::*;
+use heron::CollisionEvent;
+
+use crate::{
+    enemy::EnemyStats,
+    health::{DamageEvent, Health},
+    GameLayers, GameState,
+};
+
+use super::{player_movement::ControllablePlayer, ControlledPlayer};
+
+/// How long a player can't be hurt again after taking a hit
+pub const INVULNERABILITY_SECS: f32 = 1.0;
+const CONTACT_KNOCKBACK: f32 = 300.0;
+
+/// Enemies a player is currently touching
+#[derive(Component, Default)]
+pub struct EnemyContacts(Vec<Entity>);
+
+impl EnemyContacts {
+    pub fn clear(&mut self) {
+        self.0.clear();
+    }
+}
+
+pub fn track_enemy_contacts(
+    mut events: EventReader<CollisionEvent>,
+    mut players: Query<&mut EnemyContacts>,
+) {
+    for ev in events.iter() {
+        let (e1, e2) = ev.rigid_body_entities();
+        let (l1, l2) = ev.collision_layers();
+        let (player, enemy) = if l1.contains_group(GameLayers::Player)
+            && l2.contains_group(GameLayers::Enemies)
+        {
+            (e1, e2)
+        } else if l1.contains_group(GameLayers::Enemies) && l2.contains_group(GameLayers::Player) {
+            (e2, e1)
+        } else {
+            continue;
+        };
+
+        if let Ok(mut contacts) = players.get_mut(player) {
+            if ev.is_started() {
+                contacts.0.push(enemy);
+            } else {
+                contacts.0.retain(|&ent| ent != enemy);
+            }
+        }
+    }
+}
+
+/// Players take a hit from the hardest hitting enemy touching them. Invulnerability
+/// after each hit keeps this from firing every frame.
+pub fn enemy_contact_damage(
+    mut damage_events: EventWriter<DamageEvent>,
+    players: Query<(Entity, &Transform, &EnemyContacts)>,
+    enemies: Query<(&Transform, &EnemyStats)>,
+) {
+    for (player, player_transform, contacts) in players.iter() {
+        // Despawned enemies don't always report that they stopped touching
+        let hardest_hitter = contacts
+            .0
+            .iter()
+            .filter_map(|&enemy| enemies.get(enemy).ok().map(|stats| (enemy, stats)))
+            .max_by(|(_, (_, a)), (_, (_, b))| {
+                a.damage.partial_cmp(&b.damage).unwrap_or(Ordering::Equal)
+            });
+        if let Some((enemy, (enemy_transform, stats))) = hardest_hitter {
+            let away = (player_transform.translation - enemy_transform.translation)
+                .truncate()
+                .normalize_or_zero();
+            damage_events.send(DamageEvent {
+                source: enemy,
+                target: player,
+                amount: stats.damage,
+                knockback: away * CONTACT_KNOCKBACK,
+            });
+        }
+    }
+}
+
+pub fn player_death(
+    mut commands: Commands,
+    mut game_state: ResMut<State<GameState>>,
+    players: Query<
+        (Entity, &Health, Option<&ControlledPlayer>),
+        (With<ControllablePlayer>, Changed<Health>),
+    >,
+) {
+    for (player, health, controlled) in players.iter() {
+        if !health.is_dead() {
+            continue;
+        }
+        if controlled.is_some() {
+            info!("Player died");
+            let _ = game_state.overwrite_set(GameState::GameOver);
+        } else {
+            info!("Clone {player:?} died");
+            commands.entity(player).despawn_recursive();
+        }
+    }
+}
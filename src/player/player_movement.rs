@@ -1,15 +1,19 @@
 use bevy::prelude::*;
-use heron::{CollisionLayers, RigidBody, Velocity};
+use heron::{rapier_plugin::PhysicsWorld, RigidBody, Velocity};
 
 use crate::{
-    gun::{GunTimer, GunType},
-    inputs::PlayerInput,
+    gun::{GunTimer, GunType, ReloadTimer, WeaponDef},
+    health::{DamageEvent, Knockback},
+    inputs::{
+        bindings::{Action, ActionMap, Binding, Devices},
+        PlayerInput,
+    },
     item::{Inventory, Item},
     resources::audio_channels::AudioChannels,
     GameState,
 };
 
-use super::{ControlledPlayer, PlayerRecording, PlayerStats};
+use super::{player_dodge::Dodging, ControlledPlayer, PlayerRecording, PlayerStats};
 
 #[derive(Component, Default)]
 pub struct ControllablePlayer;
@@ -17,6 +21,8 @@ pub struct ControllablePlayer;
 #[derive(Component, Default)]
 pub struct CloneId(pub usize);
 
+/// A player's input for one fixed step, either live for the controlled player or replayed
+/// for a clone. Whatever is driven by these ticks, clones do whenever the player did.
 pub struct PlayerInputTick {
     pub entity: Entity,
     pub input: PlayerInput,
@@ -38,7 +44,14 @@ pub fn replay_recordings(
     mut player_recording: ResMut<PlayerRecording>,
     mut input_ticks: EventWriter<PlayerInputTick>,
     mut clones: Query<
-        (Entity, &mut Velocity, &PlayerStats, &CloneId),
+        (
+            Entity,
+            &mut Velocity,
+            &PlayerStats,
+            &CloneId,
+            Option<&Knockback>,
+            Option<&Dodging>,
+        ),
         (Without<ControlledPlayer>, With<RigidBody>),
     >,
 ) {
@@ -49,11 +62,15 @@ pub fn replay_recordings(
     let mut ticks_batch = vec![];
     let tick = player_recording.current_tick;
     for (id, recording) in player_recording.inputs[..current_loop].iter().enumerate() {
-        for (entity, mut vel, stat, clone_id) in clones.iter_mut() {
+        for (entity, mut vel, stat, clone_id, knockback, dodging) in clones.iter_mut() {
             if clone_id.0 == id {
+                // Being knocked back or rolling overrides walking, but not the other actions
+                let can_walk = knockback.is_none() && dodging.is_none();
                 if let Some(input) = recording.get(tick) {
                     // Movement
-                    vel.linear = Vec3::from((input.move_direction, 0.0)) * stat.speed;
+                    if can_walk {
+                        vel.linear = Vec3::from((input.move_direction, 0.0)) * stat.speed;
+                    }
                     // Shooting
                     ticks_batch.push(PlayerInputTick {
                         entity,
@@ -61,7 +78,9 @@ pub fn replay_recordings(
                     })
                 } else if let Some(input) = recording.last() {
                     // Movement
-                    vel.linear = Vec3::from((input.move_direction, 0.0)) * stat.speed;
+                    if can_walk {
+                        vel.linear = Vec3::from((input.move_direction, 0.0)) * stat.speed;
+                    }
                     // Shooting
                     ticks_batch.push(PlayerInputTick {
                         entity,
@@ -76,17 +95,38 @@ pub fn replay_recordings(
 }
 
 pub fn player_clone(
+    action_map: Res<ActionMap>,
     mut keys: ResMut<Input<KeyCode>>,
+    mut mouse: ResMut<Input<MouseButton>>,
+    gamepads: Res<Gamepads>,
+    mut gamepad_buttons: ResMut<Input<GamepadButton>>,
     mut game_state: ResMut<State<GameState>>,
     mut player_recording: ResMut<PlayerRecording>,
 ) {
-    if keys.just_pressed(KeyCode::C) {
+    let gamepad = gamepads.iter().next().copied();
+    let devices = Devices {
+        keys: &keys,
+        mouse: &mouse,
+        gamepad_buttons: &gamepad_buttons,
+        gamepad,
+    };
+    if action_map.just_pressed(Action::Clone, &devices) {
         info!("Cloning!");
         player_recording.current_loop += 1;
         player_recording.current_tick = 0;
         player_recording.inputs.push(vec![]);
         let _ = game_state.overwrite_set(GameState::SetupLevel);
-        keys.clear_just_pressed(KeyCode::C);
+        for binding in action_map.bindings(Action::Clone) {
+            match *binding {
+                Binding::Key(key) => keys.clear_just_pressed(key),
+                Binding::Mouse(button) => mouse.clear_just_pressed(button),
+                Binding::Gamepad(button_type) => {
+                    if let Some(gamepad) = gamepad {
+                        gamepad_buttons.clear_just_pressed(GamepadButton(gamepad, button_type));
+                    }
+                }
+            }
+        }
     }
 }
 
@@ -94,7 +134,12 @@ pub fn player_movement(
     player_input: Res<PlayerInput>,
     mut controllable_player: Query<
         (&mut Velocity, &PlayerStats),
-        (With<ControlledPlayer>, With<RigidBody>),
+        (
+            With<ControlledPlayer>,
+            With<RigidBody>,
+            Without<Knockback>,
+            Without<Dodging>,
+        ),
     >,
 ) {
     for (mut vel, stat) in controllable_player.iter_mut() {
@@ -115,66 +160,120 @@ pub fn player_shooting_input(
     }
 }
 
+pub fn player_switch_slot(
+    mut input_ticks: EventReader<PlayerInputTick>,
+    mut players: Query<&mut Inventory, With<ControllablePlayer>>,
+) {
+    for PlayerInputTick { input, entity } in input_ticks.iter() {
+        if input.select_slot.is_none() && input.cycle_slot == 0 {
+            continue;
+        }
+        let Ok(mut inventory) = players.get_mut(*entity) else { continue };
+        if let Some(index) = input.select_slot {
+            inventory.select(index);
+        }
+        inventory.cycle(input.cycle_slot);
+    }
+}
+
 pub fn player_shooting(
     mut commands: Commands,
     audio: Res<bevy_kira_audio::Audio>,
     channels: Res<AudioChannels>,
     asset_server: Res<AssetServer>,
+    weapons: Res<Assets<WeaponDef>>,
+    physics_world: PhysicsWorld,
+    mut damage_events: EventWriter<DamageEvent>,
     mut input_ticks: EventReader<PlayerInputTick>,
     time: Res<Time>,
-    players: Query<(Entity, &Transform, &Inventory), With<ControllablePlayer>>,
+    mut players: Query<(Entity, &Transform, &mut Inventory), With<ControllablePlayer>>,
     mut guns: Query<
         (
             &Parent,
             &mut Transform,
             &mut Visibility,
             &mut GunTimer,
-            &GunType,
+            &mut ReloadTimer,
+            &mut GunType,
         ),
         Without<ControllablePlayer>,
     >,
 ) {
     for PlayerInputTick { input, entity } in input_ticks.iter() {
         let entity = *entity;
-        let Ok((player_ent, &player_transform, inventory)) = players.get(entity) else {return};
-        for (parent, mut gun_transform, mut visibility, mut gun_timer, gun_type) in guns.iter_mut()
+        let Ok((player_ent, &player_transform, mut inventory)) =
+            players.get_mut(entity) else { return };
+        for (
+            parent,
+            mut gun_transform,
+            mut visibility,
+            mut gun_timer,
+            mut reload_timer,
+            mut gun_type,
+        ) in guns.iter_mut()
         {
-            if let Some(Item::Gun(_)) = inventory.get_item() {
+            if parent.0 != player_ent {
+                continue;
+            }
+            if let Some(Item::Gun(held_gun, ammo)) = inventory.get_item_mut() {
                 visibility.is_visible = true;
 
-                if parent.0 == player_ent {
-                    gun_timer.tick(time.delta());
-                    // Shoot
-                    if input.shoot.is_down() && gun_timer.finished() {
-                        info!("Player {player_ent:?} shoots {gun_type:?}");
-                        gun_type.play_sfx(&*audio, &channels.audio, &*asset_server);
-                        commands
-                            .spawn_bundle(gun_type.create_bullet_bundle(
-                                &*asset_server,
-                                player_transform.translation + gun_transform.translation,
-                                input.aim_direction,
-                            ))
-                            .insert(
-                                CollisionLayers::none()
-                                    .with_group(crate::GameLayers::Bullets)
-                                    .with_masks(&[
-                                        crate::GameLayers::World,
-                                        crate::GameLayers::Enemies,
-                                    ]),
-                            );
-                        gun_timer.set_duration(gun_type.cooldown());
-                        gun_timer.reset();
+                // Swap the gun in hand for whichever one was picked up
+                if *gun_type != *held_gun {
+                    *gun_type = held_gun.clone();
+                    reload_timer.0 = None;
+                }
+                // Still loading
+                let Some(weapon) = gun_type.def(&weapons) else { continue };
+                // Guns nobody has fired yet come fully loaded
+                let ammo = ammo.get_or_insert_with(|| weapon.full_ammo());
+
+                gun_timer.tick(time.delta());
+                // Reload
+                if let Some(timer) = &mut reload_timer.0 {
+                    if timer.tick(time.delta()).finished() {
+                        ammo.reload(weapon.magazine_size);
+                        reload_timer.0 = None;
                     }
-                    // Orient gun
-                    gun_transform.rotation = Quat::from_axis_angle(
-                        Vec3::Z,
-                        input.aim_direction.y.atan2(input.aim_direction.x),
+                } else if ammo.can_reload(weapon.magazine_size)
+                    && (input.reload.was_pressed() || (input.shoot.is_down() && ammo.magazine == 0))
+                {
+                    info!("Player {player_ent:?} reloads {gun_type:?}");
+                    reload_timer.0 = Some(Timer::from_seconds(weapon.reload_time, false));
+                }
+                // Shoot
+                if input.shoot.is_down()
+                    && gun_timer.finished()
+                    && reload_timer.0.is_none()
+                    && ammo.magazine > 0
+                {
+                    ammo.magazine -= 1;
+                    info!("Player {player_ent:?} shoots {gun_type:?}");
+                    weapon.play_sfx(&*audio, &channels.audio, &*asset_server);
+                    weapon.fire(
+                        &mut commands,
+                        &asset_server,
+                        &physics_world,
+                        &mut damage_events,
+                        player_ent,
+                        player_transform.translation + gun_transform.translation,
+                        input.aim_direction,
                     );
+                    gun_timer.set_duration(weapon.cooldown());
+                    gun_timer.reset();
                 }
+                // Orient gun
+                gun_transform.rotation = Quat::from_axis_angle(
+                    Vec3::Z,
+                    input.aim_direction.y.atan2(input.aim_direction.x),
+                );
             } else {
                 visibility.is_visible = false;
+                reload_timer.0 = None;
 
-                gun_timer.set_duration(gun_type.cooldown());
+                if let Some(weapon) = gun_type.def(&weapons) {
+                    gun_timer.set_duration(weapon.cooldown());
+                }
                 gun_timer.reset();
             }
         }
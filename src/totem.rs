@@ -0,0 +1,96 @@
+use bevy::prelude::*;
+
+use crate::{enemy::EnemyStats, utils::despawn_all, GameState};
+
+/// Fraction of their usual speed enemies near a totem are left with
+const SLOW_FACTOR: f32 = 0.3;
+const TOTEM_RADIUS: f32 = 120.0;
+const TOTEM_SECS: f32 = 10.0;
+
+pub struct TotemPlugin;
+
+impl Plugin for TotemPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_system_set(
+            SystemSet::on_update(GameState::Playing)
+                .with_system(slow_enemies_near_totems)
+                .with_system(expire_totems),
+        )
+        // Totems stay behind when the loop starts over otherwise
+        .add_system_set(
+            SystemSet::on_exit(GameState::Playing).with_system(despawn_all::<PlacedTotem>),
+        );
+    }
+}
+
+/// A totem set down by a player, which bogs down every enemy around it for a while
+#[derive(Component)]
+struct PlacedTotem(Timer);
+
+/// Enemies move at this fraction of their usual speed
+#[derive(Component)]
+pub struct Slowed(pub f32);
+
+pub fn place_totem(commands: &mut Commands, asset_server: &AssetServer, position: Vec3) {
+    commands
+        .spawn_bundle(SpriteBundle {
+            texture: asset_server.load("images/totem.png"),
+            transform: Transform::from_translation(position.truncate().extend(0.9)),
+            ..Default::default()
+        })
+        .insert(PlacedTotem(Timer::from_seconds(TOTEM_SECS, false)))
+        .with_children(|parent| {
+            // Shows how far it reaches
+            parent.spawn_bundle(SpriteBundle {
+                texture: asset_server.load("images/blast.png"),
+                sprite: Sprite {
+                    color: Color::rgba(0.5, 0.3, 0.9, 0.25),
+                    custom_size: Some(Vec2::splat(TOTEM_RADIUS * 2.0)),
+                    ..Default::default()
+                },
+                transform: Transform::from_xyz(0.0, 0.0, -0.05),
+                ..Default::default()
+            });
+        });
+}
+
+fn slow_enemies_near_totems(
+    mut commands: Commands,
+    totems: Query<&Transform, With<PlacedTotem>>,
+    enemies: Query<(Entity, &Transform, Option<&Slowed>), With<EnemyStats>>,
+) {
+    for (enemy, enemy_transform, slowed) in enemies.iter() {
+        let in_range = totems.iter().any(|totem_transform| {
+            totem_transform
+                .translation
+                .truncate()
+                .distance(enemy_transform.translation.truncate())
+                < TOTEM_RADIUS
+        });
+        match (in_range, slowed) {
+            (true, None) => {
+                commands.entity(enemy).insert(Slowed(SLOW_FACTOR));
+            }
+            (false, Some(_)) => {
+                commands.entity(enemy).remove::<Slowed>();
+            }
+            _ => {}
+        }
+    }
+}
+
+fn expire_totems(
+    mut commands: Commands,
+    time: Res<Time>,
+    mut totems: Query<(Entity, &mut PlacedTotem, &mut Sprite)>,
+) {
+    for (ent, mut totem, mut sprite) in totems.iter_mut() {
+        if totem.0.tick(time.delta()).finished() {
+            commands.entity(ent).despawn_recursive();
+        } else if totem.0.percent_left() < 0.2 {
+            // Blink when it's about to run out
+            let visible = (totem.0.elapsed_secs() * 8.0) as u32 % 2 == 0;
+            sprite.color.set_a(if visible { 1.0 } else { 0.3 });
+        }
+    }
+}
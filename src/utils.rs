@@ -1,4 +1,13 @@
-use bevy::{prelude::*, render::render_resource::TextureUsages};
+use std::marker::PhantomData;
+
+use bevy::{
+    asset::{Asset, AssetLoader, BoxedFuture, LoadContext, LoadedAsset},
+    prelude::*,
+    render::render_resource::TextureUsages,
+};
+use serde::de::DeserializeOwned;
+
+use crate::levels::{autotile::AutotileRules, decorations::DecorationRules};
 
 pub struct UtilsPlugin;
 
@@ -37,10 +46,67 @@ pub fn log_error(In(result): In<anyhow::Result<()>>) {
     }
 }
 
+/// An asset read straight from a RON file by a [`RonLoader`]
+pub trait RonAsset: Asset + DeserializeOwned {
+    /// Checked once the file is read, so a file edited into a bad state while the game runs
+    /// fails to load instead of breaking the game
+    fn validate(&self) -> anyhow::Result<()> {
+        Ok(())
+    }
+}
+
+/// Loads `T`s from RON files with the given extension
+pub struct RonLoader<T> {
+    extension: &'static str,
+    asset: PhantomData<fn() -> T>,
+}
+
+impl<T> RonLoader<T> {
+    pub fn new(extension: &'static str) -> Self {
+        Self {
+            extension,
+            asset: PhantomData,
+        }
+    }
+}
+
+impl<T: RonAsset> AssetLoader for RonLoader<T> {
+    fn load<'a>(
+        &'a self,
+        bytes: &'a [u8],
+        load_context: &'a mut LoadContext,
+    ) -> BoxedFuture<'a, Result<(), anyhow::Error>> {
+        Box::pin(async move {
+            let asset = ron::de::from_bytes::<T>(bytes)?;
+            asset.validate()?;
+            load_context.set_default_asset(LoadedAsset::new(asset));
+            Ok(())
+        })
+    }
+
+    fn extensions(&self) -> &[&str] {
+        std::slice::from_ref(&self.extension)
+    }
+}
+
+/// Despawns every entity with a `T`, for clearing out what's left of a level when it ends
+pub fn despawn_all<T: Component>(mut commands: Commands, query: Query<Entity, With<T>>) {
+    for ent in query.iter() {
+        commands.entity(ent).despawn_recursive();
+    }
+}
+
+/// Value following `name` on the command line, e.g. `--seed 42`
+pub fn arg_value(name: &str) -> Option<String> {
+    std::env::args().skip_while(|arg| arg != name).nth(1)
+}
+
 /// Where we can store commonly used handles
 /// instead of always using asset server
 pub struct CommonHandles {
     pub player_sprites: Handle<TextureAtlas>,
+    pub autotile_rules: Handle<AutotileRules>,
+    pub decoration_rules: Handle<DecorationRules>,
 }
 
 pub fn load_common_handles(
@@ -53,6 +119,12 @@ pub fn load_common_handles(
     let player_sprites_atlas =
         TextureAtlas::from_grid(player_sprites_tex, Vec2::new(32.0, 32.0), 8, 8);
     let player_sprites = texture_atlases.add(player_sprites_atlas);
-    commands.insert_resource(CommonHandles { player_sprites });
+    let autotile_rules = asset_server.load("tilesets/tomb.autotile.ron");
+    let decoration_rules = asset_server.load("tilesets/tomb.decor.ron");
+    commands.insert_resource(CommonHandles {
+        player_sprites,
+        autotile_rules,
+        decoration_rules,
+    });
     info!("Common handles loaded!");
 }